@@ -0,0 +1,16 @@
+use std::path::Path;
+
+use candid_parser::utils::CandidSource;
+
+/// Interface of the NFT canister, see `Contract`
+const CANISTER_DID: &str = "candid/nft.did";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", CANISTER_DID);
+
+    // A .did that no longer parses or type checks fails the build rather
+    // than the drift test
+    if let Err(err) = CandidSource::File(Path::new(CANISTER_DID)).load() {
+        panic!("Invalid canister interface {}: {}", CANISTER_DID, err);
+    }
+}
@@ -15,12 +15,22 @@ pub struct Model {
     #[sea_orm(column_type = "Double")]
     pub size_mb: f64,
     pub content_type: String,
+    pub detected_content_type: Option<String>,
     #[sea_orm(unique)]
     pub ipfs_hash: String,
     #[sea_orm(unique)]
     pub nft_id: i64,
     pub client_id: i64,
     pub folder_id: i64,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub metadata: Option<Json>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub encrypted_data_key: Option<String>,
+    pub encryption_key_id: Option<String>,
+    pub plaintext_sha256: Option<String>,
+    pub owner_principal: Option<String>,
+    pub mint_txn_id: Option<String>,
+    pub minted_at: DateTime,
     pub date_added: DateTime,
     pub last_updated: DateTime,
 }
@@ -14,6 +14,10 @@ pub struct Model {
     pub logo_hash: String,
     pub description: String,
     pub client_id: i64,
+    pub parent_id: Option<i64>,
+    pub encrypted: bool,
+    pub mint_to_wallet: bool,
+    pub create_txn_id: Option<String>,
     pub date_added: DateTime,
     pub last_updated: DateTime,
 }
@@ -3,13 +3,21 @@
 pub mod prelude;
 
 pub mod asset;
+pub mod asset_transfer;
 pub mod auth_token;
 pub mod client;
 pub mod client_auth_token;
 pub mod client_monthly_requests;
 pub mod client_package_subscription;
 pub mod client_usage;
+pub mod export_job;
 pub mod folder;
 pub mod profile;
+pub mod rate_limit_bucket;
+pub mod reconciliation_issue;
+pub mod reconciliation_job;
+pub mod security_event;
 pub mod subscription_package;
+pub mod thumbnail;
 pub mod user;
+pub mod wallet_challenge;
@@ -1,13 +1,21 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.1
 
 pub use super::asset::Entity as Asset;
+pub use super::asset_transfer::Entity as AssetTransfer;
 pub use super::auth_token::Entity as AuthToken;
 pub use super::client::Entity as Client;
 pub use super::client_auth_token::Entity as ClientAuthToken;
 pub use super::client_monthly_requests::Entity as ClientMonthlyRequests;
 pub use super::client_package_subscription::Entity as ClientPackageSubscription;
 pub use super::client_usage::Entity as ClientUsage;
+pub use super::export_job::Entity as ExportJob;
 pub use super::folder::Entity as Folder;
 pub use super::profile::Entity as Profile;
+pub use super::rate_limit_bucket::Entity as RateLimitBucket;
+pub use super::reconciliation_issue::Entity as ReconciliationIssue;
+pub use super::reconciliation_job::Entity as ReconciliationJob;
+pub use super::security_event::Entity as SecurityEvent;
 pub use super::subscription_package::Entity as SubscriptionPackage;
+pub use super::thumbnail::Entity as Thumbnail;
 pub use super::user::Entity as User;
+pub use super::wallet_challenge::Entity as WalletChallenge;
@@ -17,6 +17,14 @@ pub struct Model {
     pub storage_capacity_mb: f64,
     pub monthly_requests: i64,
     pub max_allowed_sessions: i32,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub max_file_size_mb: Option<f64>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub allowed_content_types: Option<Json>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub denied_content_types: Option<Json>,
+    pub max_assets_per_folder: Option<i32>,
+    pub max_folders: Option<i32>,
     pub date_added: DateTime,
     pub last_updated: DateTime,
 }
@@ -11,9 +11,14 @@ pub struct Model {
     #[sea_orm(unique)]
     pub uuid: Uuid,
     #[sea_orm(unique)]
-    pub email: String,
+    pub email: Option<String>,
+    #[sea_orm(unique)]
     pub wallet_address: Option<String>,
     pub password_hash: Option<String>,
+    pub is_admin: bool,
+    pub failed_signin_attempts: i32,
+    pub last_failed_signin_at: Option<DateTime>,
+    pub locked_until: Option<DateTime>,
     pub date_added: DateTime,
     pub last_updated: DateTime,
 }
@@ -26,6 +31,8 @@ pub enum Relation {
     Client,
     #[sea_orm(has_one = "super::profile::Entity")]
     Profile,
+    #[sea_orm(has_many = "super::security_event::Entity")]
+    SecurityEvent,
 }
 
 impl Related<super::auth_token::Entity> for Entity {
@@ -46,4 +53,10 @@ impl Related<super::profile::Entity> for Entity {
     }
 }
 
+impl Related<super::security_event::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SecurityEvent.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,65 @@
+//! Signs a wallet challenge with a freshly generated identity and prints the
+//! `WalletSignatureInput` for `walletSignin` or `linkWallet`.
+//!
+//! `cargo run --example sign_wallet_challenge -- <nonce> <message> [--delegate]`
+//!
+//! The root identity is secp256k1. With `--delegate` it delegates to an
+//! Ed25519 session key which signs the challenge, like agent-js sessions do.
+
+use candid::Principal;
+use ic_agent::{
+    identity::{Delegation, Secp256k1Identity},
+    Identity,
+};
+use k256::elliptic_curve::rand_core::OsRng;
+
+const ED25519_DER_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (nonce, message) = match (args.first(), args.get(1)) {
+        (Some(nonce), Some(message)) => (nonce, message),
+        _ => return Err("Usage: sign_wallet_challenge <nonce> <message> [--delegate]".into()),
+    };
+    let delegate = args.iter().any(|arg| arg == "--delegate");
+
+    let root = Secp256k1Identity::from_private_key(k256::SecretKey::random(&mut OsRng));
+    let public_key = root.public_key().ok_or("Identity has no public key")?;
+
+    let (signature, delegations) = if delegate {
+        let session = ed25519_consensus::SigningKey::new(OsRng);
+        let mut session_public_key = ED25519_DER_PREFIX.to_vec();
+        session_public_key.extend_from_slice(session.verification_key().as_bytes());
+
+        let expiration = chrono::Utc::now() + std::time::Duration::from_secs(60 * 60);
+        let delegation = Delegation {
+            pubkey: session_public_key.clone(),
+            expiration: expiration.timestamp_nanos_opt().unwrap_or(i64::MAX) as u64,
+            targets: None,
+        };
+        let delegation_signature = root.sign_delegation(&delegation)?.signature;
+
+        let signature = session.sign(message.as_bytes()).to_bytes().to_vec();
+        let delegations = serde_json::json!([{
+            "pubkey": hex::encode(&session_public_key),
+            "expiration": format!("{:x}", delegation.expiration),
+            "signature": hex::encode(delegation_signature.unwrap_or_default()),
+        }]);
+        (signature, delegations)
+    } else {
+        let signature = root.sign_arbitrary(message.as_bytes())?.signature;
+        (signature.unwrap_or_default(), serde_json::Value::Null)
+    };
+
+    let input = serde_json::json!({
+        "nonce": nonce,
+        "publicKey": hex::encode(&public_key),
+        "signature": hex::encode(signature),
+        "delegations": delegations,
+    });
+    eprintln!("Principal: {}", Principal::self_authenticating(&public_key));
+    println!("{}", input);
+    Ok(())
+}
@@ -5,6 +5,21 @@ mod m20241204_111833_create_user_tables;
 mod m20241204_122105_create_client_and_package_tables;
 mod m20241205_070110_create_asset_table;
 mod m20241205_081228_create_auth_tables;
+mod m20241210_083015_add_folder_parent;
+mod m20241212_101540_create_export_job_table;
+mod m20241214_094210_create_thumbnail_table;
+mod m20241216_110402_add_asset_metadata;
+mod m20241217_085517_add_asset_detected_content_type;
+mod m20241218_093044_add_package_upload_policies;
+mod m20241219_140312_add_asset_encryption;
+mod m20241220_101215_create_asset_transfer_table;
+mod m20241221_083420_add_folder_mint_to_wallet;
+mod m20241222_091530_create_wallet_challenge_table;
+mod m20241223_102045_create_reconciliation_tables;
+mod m20241224_081205_add_txn_ids;
+mod m20241225_090412_create_rate_limit_bucket_table;
+mod m20241226_101830_add_signin_lockout;
+mod m20241227_093310_add_asset_minted_at;
 
 pub struct Migrator;
 
@@ -16,6 +31,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20241204_122105_create_client_and_package_tables::Migration),
             Box::new(m20241205_070110_create_asset_table::Migration),
             Box::new(m20241205_081228_create_auth_tables::Migration),
+            Box::new(m20241210_083015_add_folder_parent::Migration),
+            Box::new(m20241212_101540_create_export_job_table::Migration),
+            Box::new(m20241214_094210_create_thumbnail_table::Migration),
+            Box::new(m20241216_110402_add_asset_metadata::Migration),
+            Box::new(m20241217_085517_add_asset_detected_content_type::Migration),
+            Box::new(m20241218_093044_add_package_upload_policies::Migration),
+            Box::new(m20241219_140312_add_asset_encryption::Migration),
+            Box::new(m20241220_101215_create_asset_transfer_table::Migration),
+            Box::new(m20241221_083420_add_folder_mint_to_wallet::Migration),
+            Box::new(m20241222_091530_create_wallet_challenge_table::Migration),
+            Box::new(m20241223_102045_create_reconciliation_tables::Migration),
+            Box::new(m20241224_081205_add_txn_ids::Migration),
+            Box::new(m20241225_090412_create_rate_limit_bucket_table::Migration),
+            Box::new(m20241226_101830_add_signin_lockout::Migration),
+            Box::new(m20241227_093310_add_asset_minted_at::Migration),
         ]
     }
 }
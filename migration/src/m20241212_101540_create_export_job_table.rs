@@ -0,0 +1,101 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    m20241204_122105_create_client_and_package_tables::Client,
+    m20241205_070110_create_asset_table::Folder, utils::default_uuid,
+};
+
+const EXPORT_JOB_CLIENT_FK: &str = "fk-export-job-client";
+const EXPORT_JOB_FOLDER_FK: &str = "fk-export-job-folder";
+const EXPORT_JOB_UUID_INDEX: &str = "idx-export-job-uuid";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExportJob::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ExportJob::Id))
+                    .col(
+                        uuid(ExportJob::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer(ExportJob::ClientId))
+                    .col(big_integer_null(ExportJob::FolderId))
+                    .col(string(ExportJob::Status))
+                    .col(integer(ExportJob::AssetCount).default(Value::Int(Some(0))))
+                    .col(string_null(ExportJob::FilePath))
+                    .col(string_null(ExportJob::Error))
+                    .col(date_time(ExportJob::DateAdded).default(Expr::current_timestamp()))
+                    .col(date_time_null(ExportJob::CompletedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(EXPORT_JOB_CLIENT_FK)
+                            .from(ExportJob::Table, ExportJob::ClientId)
+                            .to(Client::Table, Client::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(EXPORT_JOB_FOLDER_FK)
+                            .from(ExportJob::Table, ExportJob::FolderId)
+                            .to(Folder::Table, Folder::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(EXPORT_JOB_UUID_INDEX)
+                    .if_not_exists()
+                    .table(ExportJob::Table)
+                    .col(ExportJob::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(EXPORT_JOB_UUID_INDEX)
+                    .if_exists()
+                    .table(ExportJob::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ExportJob::Table).if_exists().to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ExportJob {
+    Table,
+    Id,
+    Uuid,
+    ClientId,
+    FolderId,
+    Status,
+    AssetCount,
+    FilePath,
+    Error,
+    DateAdded,
+    CompletedAt,
+}
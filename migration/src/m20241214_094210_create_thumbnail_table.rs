@@ -0,0 +1,101 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    m20241205_070110_create_asset_table::{Asset, Folder},
+    utils::default_uuid,
+};
+
+const THUMBNAIL_ASSET_FK: &str = "fk-thumbnail-asset";
+const THUMBNAIL_FOLDER_FK: &str = "fk-thumbnail-folder";
+const THUMBNAIL_UUID_INDEX: &str = "idx-thumbnail-uuid";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Thumbnail::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Thumbnail::Id))
+                    .col(
+                        uuid(Thumbnail::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer_null(Thumbnail::AssetId))
+                    .col(big_integer_null(Thumbnail::FolderId))
+                    .col(integer(Thumbnail::Size))
+                    .col(integer(Thumbnail::Width))
+                    .col(integer(Thumbnail::Height))
+                    .col(string(Thumbnail::ContentType))
+                    .col(string(Thumbnail::IpfsHash))
+                    .col(date_time(Thumbnail::DateAdded).default(Expr::current_timestamp()))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(THUMBNAIL_ASSET_FK)
+                            .from(Thumbnail::Table, Thumbnail::AssetId)
+                            .to(Asset::Table, Asset::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(THUMBNAIL_FOLDER_FK)
+                            .from(Thumbnail::Table, Thumbnail::FolderId)
+                            .to(Folder::Table, Folder::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(THUMBNAIL_UUID_INDEX)
+                    .if_not_exists()
+                    .table(Thumbnail::Table)
+                    .col(Thumbnail::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(THUMBNAIL_UUID_INDEX)
+                    .if_exists()
+                    .table(Thumbnail::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Thumbnail::Table).if_exists().to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Thumbnail {
+    Table,
+    Id,
+    Uuid,
+    AssetId,
+    FolderId,
+    Size,
+    Width,
+    Height,
+    ContentType,
+    IpfsHash,
+    DateAdded,
+}
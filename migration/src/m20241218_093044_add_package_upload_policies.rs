@@ -0,0 +1,51 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionPackage::Table)
+                    .add_column(double_null(SubscriptionPackage::MaxFileSizeMb))
+                    .add_column(json_binary_null(SubscriptionPackage::AllowedContentTypes))
+                    .add_column(json_binary_null(SubscriptionPackage::DeniedContentTypes))
+                    .add_column(integer_null(SubscriptionPackage::MaxAssetsPerFolder))
+                    .add_column(integer_null(SubscriptionPackage::MaxFolders))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionPackage::Table)
+                    .drop_column(SubscriptionPackage::MaxFileSizeMb)
+                    .drop_column(SubscriptionPackage::AllowedContentTypes)
+                    .drop_column(SubscriptionPackage::DeniedContentTypes)
+                    .drop_column(SubscriptionPackage::MaxAssetsPerFolder)
+                    .drop_column(SubscriptionPackage::MaxFolders)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum SubscriptionPackage {
+    Table,
+    MaxFileSizeMb,
+    AllowedContentTypes,
+    DeniedContentTypes,
+    MaxAssetsPerFolder,
+    MaxFolders,
+}
@@ -0,0 +1,69 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Folder::Table)
+                    .add_column(boolean(Folder::Encrypted).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Asset::Table)
+                    .add_column(text_null(Asset::EncryptedDataKey))
+                    .add_column(string_null(Asset::EncryptionKeyId))
+                    .add_column(string_null(Asset::PlaintextSha256))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Asset::Table)
+                    .drop_column(Asset::EncryptedDataKey)
+                    .drop_column(Asset::EncryptionKeyId)
+                    .drop_column(Asset::PlaintextSha256)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Folder::Table)
+                    .drop_column(Folder::Encrypted)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Folder {
+    Table,
+    Encrypted,
+}
+
+#[derive(DeriveIden)]
+enum Asset {
+    Table,
+    EncryptedDataKey,
+    EncryptionKeyId,
+    PlaintextSha256,
+}
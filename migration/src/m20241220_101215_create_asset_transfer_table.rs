@@ -0,0 +1,115 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::utils::default_uuid;
+
+const ASSET_TRANSFER_ASSET_FK: &str = "fk-asset-transfer-asset";
+const ASSET_TRANSFER_UUID_INDEX: &str = "idx-asset-transfer-uuid";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Asset::Table)
+                    .add_column(string_null(Asset::OwnerPrincipal))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AssetTransfer::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AssetTransfer::Id))
+                    .col(
+                        uuid(AssetTransfer::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer(AssetTransfer::AssetId))
+                    .col(string_null(AssetTransfer::FromPrincipal))
+                    .col(string(AssetTransfer::ToPrincipal))
+                    .col(string(AssetTransfer::TxnId))
+                    .col(date_time(AssetTransfer::DateAdded).default(Expr::current_timestamp()))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(ASSET_TRANSFER_ASSET_FK)
+                            .from(AssetTransfer::Table, AssetTransfer::AssetId)
+                            .to(Asset::Table, Asset::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(ASSET_TRANSFER_UUID_INDEX)
+                    .if_not_exists()
+                    .table(AssetTransfer::Table)
+                    .col(AssetTransfer::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(ASSET_TRANSFER_UUID_INDEX)
+                    .if_exists()
+                    .table(AssetTransfer::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(AssetTransfer::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Asset::Table)
+                    .drop_column(Asset::OwnerPrincipal)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Asset {
+    Table,
+    Id,
+    OwnerPrincipal,
+}
+
+#[derive(DeriveIden)]
+pub enum AssetTransfer {
+    Table,
+    Id,
+    Uuid,
+    AssetId,
+    FromPrincipal,
+    ToPrincipal,
+    TxnId,
+    DateAdded,
+}
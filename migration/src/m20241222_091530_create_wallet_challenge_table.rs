@@ -0,0 +1,145 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::utils::default_uuid;
+
+const WALLET_CHALLENGE_UUID_INDEX: &str = "idx-wallet-challenge-uuid";
+const USER_WALLET_ADDRESS_INDEX: &str = "idx-user-wallet-address";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WalletChallenge::Table)
+                    .if_not_exists()
+                    .col(pk_auto(WalletChallenge::Id))
+                    .col(
+                        uuid(WalletChallenge::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(text(WalletChallenge::Message))
+                    .col(date_time(WalletChallenge::ExpiresAt))
+                    .col(date_time(WalletChallenge::DateAdded).default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(WALLET_CHALLENGE_UUID_INDEX)
+                    .if_not_exists()
+                    .table(WalletChallenge::Table)
+                    .col(WalletChallenge::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Wallet users sign in without an email
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .modify_column(ColumnDef::new(User::Email).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(USER_WALLET_ADDRESS_INDEX)
+                    .if_not_exists()
+                    .table(User::Table)
+                    .col(User::WalletAddress)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(USER_WALLET_ADDRESS_INDEX)
+                    .if_exists()
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Wallet users get a placeholder email, which no mail is delivered to,
+        // rather than being deleted with everything they own
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(User::Table)
+                    .value(
+                        User::Email,
+                        Func::cust(Concat).args([
+                            Expr::col(User::WalletAddress).into(),
+                            Expr::val("@wallet.invalid").into(),
+                        ]),
+                    )
+                    .and_where(Expr::col(User::Email).is_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .modify_column(ColumnDef::new(User::Email).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(WALLET_CHALLENGE_UUID_INDEX)
+                    .if_exists()
+                    .table(WalletChallenge::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(WalletChallenge::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+struct Concat;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Email,
+    WalletAddress,
+}
+
+#[derive(DeriveIden)]
+pub enum WalletChallenge {
+    Table,
+    Id,
+    Uuid,
+    Message,
+    ExpiresAt,
+    DateAdded,
+}
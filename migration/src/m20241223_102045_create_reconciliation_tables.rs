@@ -0,0 +1,215 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::{
+    m20241204_122105_create_client_and_package_tables::Client,
+    m20241205_070110_create_asset_table::Asset, utils::default_uuid,
+};
+
+const RECONCILIATION_JOB_CLIENT_FK: &str = "fk-reconciliation-job-client";
+const RECONCILIATION_JOB_UUID_INDEX: &str = "idx-reconciliation-job-uuid";
+const RECONCILIATION_ISSUE_JOB_FK: &str = "fk-reconciliation-issue-job";
+const RECONCILIATION_ISSUE_ASSET_FK: &str = "fk-reconciliation-issue-asset";
+const RECONCILIATION_ISSUE_UUID_INDEX: &str = "idx-reconciliation-issue-uuid";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(boolean(User::IsAdmin).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReconciliationJob::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ReconciliationJob::Id))
+                    .col(
+                        uuid(ReconciliationJob::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer_null(ReconciliationJob::ClientId))
+                    .col(string(ReconciliationJob::Status))
+                    .col(integer(ReconciliationJob::AssetCount).default(Value::Int(Some(0))))
+                    .col(integer(ReconciliationJob::ConsistentCount).default(Value::Int(Some(0))))
+                    .col(integer(ReconciliationJob::IssueCount).default(Value::Int(Some(0))))
+                    .col(string_null(ReconciliationJob::Error))
+                    .col(date_time(ReconciliationJob::DateAdded).default(Expr::current_timestamp()))
+                    .col(date_time_null(ReconciliationJob::CompletedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(RECONCILIATION_JOB_CLIENT_FK)
+                            .from(ReconciliationJob::Table, ReconciliationJob::ClientId)
+                            .to(Client::Table, Client::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(RECONCILIATION_JOB_UUID_INDEX)
+                    .if_not_exists()
+                    .table(ReconciliationJob::Table)
+                    .col(ReconciliationJob::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReconciliationIssue::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ReconciliationIssue::Id))
+                    .col(
+                        uuid(ReconciliationIssue::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer(ReconciliationIssue::JobId))
+                    .col(string(ReconciliationIssue::Kind))
+                    .col(big_integer_null(ReconciliationIssue::AssetId))
+                    .col(big_integer(ReconciliationIssue::CollectionId))
+                    .col(string_null(ReconciliationIssue::TokenId))
+                    .col(string_null(ReconciliationIssue::Detail))
+                    .col(string_null(ReconciliationIssue::Resolution))
+                    .col(date_time_null(ReconciliationIssue::ResolvedAt))
+                    .col(
+                        date_time(ReconciliationIssue::DateAdded)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(RECONCILIATION_ISSUE_JOB_FK)
+                            .from(ReconciliationIssue::Table, ReconciliationIssue::JobId)
+                            .to(ReconciliationJob::Table, ReconciliationJob::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(RECONCILIATION_ISSUE_ASSET_FK)
+                            .from(ReconciliationIssue::Table, ReconciliationIssue::AssetId)
+                            .to(Asset::Table, Asset::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(RECONCILIATION_ISSUE_UUID_INDEX)
+                    .if_not_exists()
+                    .table(ReconciliationIssue::Table)
+                    .col(ReconciliationIssue::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(RECONCILIATION_ISSUE_UUID_INDEX)
+                    .if_exists()
+                    .table(ReconciliationIssue::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ReconciliationIssue::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(RECONCILIATION_JOB_UUID_INDEX)
+                    .if_exists()
+                    .table(ReconciliationJob::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ReconciliationJob::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::IsAdmin)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    IsAdmin,
+}
+
+#[derive(DeriveIden)]
+pub enum ReconciliationJob {
+    Table,
+    Id,
+    Uuid,
+    ClientId,
+    Status,
+    AssetCount,
+    ConsistentCount,
+    IssueCount,
+    Error,
+    DateAdded,
+    CompletedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum ReconciliationIssue {
+    Table,
+    Id,
+    Uuid,
+    JobId,
+    Kind,
+    AssetId,
+    CollectionId,
+    TokenId,
+    Detail,
+    Resolution,
+    ResolvedAt,
+    DateAdded,
+}
@@ -0,0 +1,65 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+const RATE_LIMIT_BUCKET_EXPIRES_AT_INDEX: &str = "idx-rate-limit-bucket-expires-at";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RateLimitBucket::Table)
+                    .if_not_exists()
+                    .col(string(RateLimitBucket::Key).primary_key())
+                    .col(double(RateLimitBucket::Tokens))
+                    .col(date_time(RateLimitBucket::UpdatedAt))
+                    .col(date_time(RateLimitBucket::ExpiresAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(RATE_LIMIT_BUCKET_EXPIRES_AT_INDEX)
+                    .if_not_exists()
+                    .table(RateLimitBucket::Table)
+                    .col(RateLimitBucket::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(RATE_LIMIT_BUCKET_EXPIRES_AT_INDEX)
+                    .if_exists()
+                    .table(RateLimitBucket::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(RateLimitBucket::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum RateLimitBucket {
+    Table,
+    Key,
+    Tokens,
+    UpdatedAt,
+    ExpiresAt,
+}
@@ -0,0 +1,121 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::utils::default_uuid;
+
+const SECURITY_EVENT_USER_FK: &str = "fk-security-event-user";
+const SECURITY_EVENT_UUID_INDEX: &str = "idx-security-event-uuid";
+const SECURITY_EVENT_USER_INDEX: &str = "idx-security-event-user";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(integer(User::FailedSigninAttempts).default(Value::Int(Some(0))))
+                    .add_column(date_time_null(User::LastFailedSigninAt))
+                    .add_column(date_time_null(User::LockedUntil))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SecurityEvent::Table)
+                    .if_not_exists()
+                    .col(pk_auto(SecurityEvent::Id))
+                    .col(
+                        uuid(SecurityEvent::Uuid)
+                            .unique_key()
+                            .default(Value::Uuid(default_uuid())),
+                    )
+                    .col(big_integer_null(SecurityEvent::UserId))
+                    .col(string(SecurityEvent::Kind))
+                    .col(string_null(SecurityEvent::IpAddress))
+                    .col(text_null(SecurityEvent::Detail))
+                    .col(date_time(SecurityEvent::DateAdded).default(Expr::current_timestamp()))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name(SECURITY_EVENT_USER_FK)
+                            .from(SecurityEvent::Table, SecurityEvent::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(SECURITY_EVENT_UUID_INDEX)
+                    .if_not_exists()
+                    .table(SecurityEvent::Table)
+                    .col(SecurityEvent::Uuid)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(SECURITY_EVENT_USER_INDEX)
+                    .if_not_exists()
+                    .table(SecurityEvent::Table)
+                    .col(SecurityEvent::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(SecurityEvent::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::LockedUntil)
+                    .drop_column(User::LastFailedSigninAt)
+                    .drop_column(User::FailedSigninAttempts)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    FailedSigninAttempts,
+    LastFailedSigninAt,
+    LockedUntil,
+}
+
+#[derive(DeriveIden)]
+pub enum SecurityEvent {
+    Table,
+    Id,
+    Uuid,
+    UserId,
+    Kind,
+    IpAddress,
+    Detail,
+    DateAdded,
+}
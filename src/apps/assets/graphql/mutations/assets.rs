@@ -1,25 +1,40 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use async_graphql::*;
 use chrono::Utc;
-use entity::entities::{
-    asset, client, client_package_subscription, client_usage, folder, subscription_package, user,
-};
-use sea_orm::{
-    entity::*, DatabaseConnection, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QuerySelect,
-};
+use entity::entities::{asset, asset_transfer, client, folder, user};
+use futures::{stream, StreamExt};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
 use uuid::Uuid;
 
-use crate::apps::assets::{
-    graphql::types::{
-        inputs::assets::{AssetInput, FolderInput},
-        outputs::assets::{AssetType, FolderType},
+use crate::{
+    apps::assets::{
+        graphql::types::{
+            inputs::assets::{
+                AssetArchiveImportInput, AssetInput, AssetTransferInput, BatchAssetInput,
+                FolderInput,
+            },
+            outputs::assets::{
+                AssetArchiveImportType, AssetTransferType, AssetType, BatchAssetResultType,
+                FolderType, SkippedArchiveEntryType,
+            },
+        },
+        utils::{
+            archives::AssetArchive,
+            contract::{parse_principal, Contract, MintNFTResult, TokenId, TransferNFTResult},
+            files::{bytes_to_mb, sniff_content_type},
+            pinata::Pinata,
+            quota::{ClientQuota, UploadPolicy},
+            thumbnails::{delete_thumbnails, save_thumbnails, ThumbnailOwner},
+            uploads::{
+                create_asset, create_folder, find_or_create_child_folder, mint_owner,
+                prepare_content, store_content, FolderOptions, PendingAsset, PreparedContent,
+                BATCH_UPLOAD_CONCURRENCY, MAX_BATCH_UPLOAD_FILES,
+            },
+        },
     },
-    utils::{
-        contract::{Contract, CreateNFTResult, MintNFTResult},
-        files::bytes_to_mb,
-        formating::format_id,
-        pinata::Pinata,
+    config::{
+        metrics::record_upload, rate_limit::RateLimiter, state::AppState, telemetry::record_client,
     },
 };
 
@@ -34,6 +49,7 @@ impl AssetMutations {
         input: FolderInput,
     ) -> Result<FolderType> {
         let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
         let user = ctx.data::<Option<user::Model>>()?;
 
         if let Some(user) = user {
@@ -43,6 +59,7 @@ impl AssetMutations {
                 .await?;
 
             if let Some(client) = client {
+                record_client(client.id);
                 if let Some(uuid) = input.uuid {
                     let folder = folder::Entity::find()
                         .filter(folder::Column::Uuid.eq(Uuid::from_str(uuid.to_string().as_str())?))
@@ -57,6 +74,9 @@ impl AssetMutations {
                         let mut folder: folder::ActiveModel = folder.into();
                         folder.name = Set(input.name);
                         folder.description = Set(input.description);
+                        if let Some(mint_to_wallet) = input.mint_to_wallet {
+                            folder.mint_to_wallet = Set(mint_to_wallet);
+                        }
                         folder.last_updated = Set(Utc::now().naive_utc());
 
                         let folder = folder.update(db).await?;
@@ -68,42 +88,52 @@ impl AssetMutations {
                         )))
                     }
                 } else {
+                    ctx.data::<Arc<RateLimiter>>()?
+                        .check_upload(ctx, client.id)
+                        .await?;
                     let value = input.logo.value(ctx)?;
+                    let logo_size_mb = bytes_to_mb(value.size()?);
                     if let Some(content_type) = value.content_type {
-                        if !content_type.starts_with("image") {
-                            return Err(Error::new("Please provide a valid image"));
+                        let policy = UploadPolicy::for_client(db, client.id).await?;
+                        policy.check_folders(db, client.id as i64, 1).await?;
+                        policy.check_file_size(logo_size_mb)?;
+                        let encrypted = input.encrypted.unwrap_or(false);
+                        if encrypted {
+                            // Fail before the folder exists rather than on its first upload
+                            state.key_provider()?;
                         }
 
-                        let pinata_res = Pinata::pin_file(value.content).await?;
-                        let logo_url = Some(Pinata::build_url(pinata_res.ipfs_hash.clone()));
-                        let count = folder::Entity::find().count(db).await?;
-                        let symbol = format_id(count + 1);
+                        let mut content = value.content;
+                        let sniffed = sniff_content_type(state, Some(&content_type), &mut content)?;
+                        if !sniffed.detected.starts_with("image") {
+                            return Err(Error::new("Please provide a valid image"));
+                        }
 
-                        let result = Contract::create_nft(
-                            &input.name,
-                            &symbol,
-                            &input.description,
-                            &logo_url,
+                        let prepared =
+                            prepare_content(content, &sniffed.detected, true, false).await?;
+                        let pinata_res = Pinata::pin_file(state, prepared.content).await?;
+                        let folder = create_folder(
+                            db,
+                            state,
+                            client.id,
+                            None,
+                            input.name,
+                            input.description,
+                            pinata_res.ipfs_hash,
+                            FolderOptions {
+                                encrypted,
+                                mint_to_wallet: input.mint_to_wallet.unwrap_or(false),
+                            },
                         )
                         .await?;
-
-                        if let CreateNFTResult::Ok(res) = result {
-                            let folder = folder::ActiveModel {
-                                id: Set(res.1.id as i32),
-                                uuid: Set(Uuid::new_v4()),
-                                name: Set(input.name),
-                                logo_hash: Set(pinata_res.ipfs_hash),
-                                description: Set(input.description),
-                                client_id: Set(client.id as i64),
-                                ..Default::default()
-                            };
-                            let folder = folder.insert(db).await?;
-                            Ok(folder.into())
-                        } else if let CreateNFTResult::Err(err) = result {
-                            return Err(Error::new(format!("Contract error: {}", err)));
-                        } else {
-                            return Err(Error::new("Failed to create NFT"));
-                        }
+                        save_thumbnails(
+                            db,
+                            state,
+                            ThumbnailOwner::Folder(folder.id),
+                            prepared.thumbnails,
+                        )
+                        .await;
+                        Ok(folder.into())
                     } else {
                         Err(Error::new("Unable to verify image type"))
                     }
@@ -126,6 +156,7 @@ impl AssetMutations {
         input: AssetInput,
     ) -> Result<AssetType> {
         let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
         let user = ctx.data::<Option<user::Model>>()?;
         let folder = folder::Entity::find()
             .filter(folder::Column::Uuid.eq(Uuid::from_str(input.folder_uuid.as_str())?))
@@ -134,168 +165,152 @@ impl AssetMutations {
 
         if let Some(folder) = folder {
             if let Some(user) = user {
-                let user_client = client::Entity::find()
-                    .filter(client::Column::UserId.eq(user.id))
-                    .one(db)
-                    .await?;
-
-                if user_client.is_none() {
-                    return Err(Error::new("User Client not found"));
+                let quota = ClientQuota::for_user(user, db).await?;
+                if folder.client_id != quota.client.id as i64 {
+                    return Err(Error::new("You are not authorized to perform this action"));
                 }
-
-                let client_package = client::Entity::find()
-                    .filter(client::Column::UserId.eq(user.id))
-                    .join(
-                        JoinType::InnerJoin,
-                        client::Relation::ClientPackageSubscription.def(),
-                    )
-                    .join(
-                        JoinType::InnerJoin,
-                        client_package_subscription::Relation::SubscriptionPackage.def(),
-                    )
-                    .select_also(subscription_package::Entity)
-                    .one(db)
+                ctx.data::<Arc<RateLimiter>>()?
+                    .check_upload(ctx, quota.client.id)
                     .await?;
+                let policy = quota.policy()?;
+                let user_client = &quota.client;
 
-                if let Some(client_package) = client_package {
-                    let user_client = client_package.0;
+                let file_value = input.file.value(ctx)?;
+                let file_size = file_value.size()?;
+                let size_in_mb = bytes_to_mb(file_size);
 
-                    let client_usage = match client_usage::Entity::find()
-                        .filter(client_usage::Column::ClientId.eq(user_client.id))
+                if let Some(uuid) = input.uuid {
+                    let asset = asset::Entity::find()
+                        .filter(asset::Column::Uuid.eq(Uuid::from_str(uuid.to_string().as_str())?))
                         .one(db)
-                        .await?
-                    {
-                        Some(usage) => usage,
-                        None => {
-                            client_usage::ActiveModel {
-                                uuid: Set(Uuid::new_v4()),
-                                client_id: Set(user_client.id.into()),
-                                used_storage_mb: Set(0.0),
-                                active_sessions: Set(0),
-                                ..Default::default()
-                            }
-                            .insert(db)
-                            .await?
+                        .await?;
+
+                    if let Some(asset) = asset {
+                        if asset.client_id != user_client.id as i64 {
+                            return Err(Error::new(
+                                "You are not authorized to perform this action",
+                            ));
+                        }
+                        if !held_by_backend(state, &asset)? {
+                            // Burning needs the token, which now belongs to another wallet
+                            return Err(Error::new(
+                                "Assets held by another wallet can no longer be replaced",
+                            ));
+                        }
+                        let owner = mint_owner(
+                            user,
+                            &folder,
+                            input.recipient_principal.as_deref(),
+                            input.mint_to_wallet,
+                        )?;
+                        quota.check_storage(size_in_mb, asset.size_mb)?;
+                        policy.check_file_size(size_in_mb)?;
+                        if asset.folder_id != folder.id as i64 {
+                            policy.check_folder_assets(db, &folder, 1).await?;
                         }
-                    };
 
-                    if let Some(package) = client_package.1 {
-                        let file_value = input.file.value(ctx)?;
-                        let file_size = file_value.size()?;
-                        let size_in_mb = bytes_to_mb(file_size);
-
-                        if let Some(uuid) = input.uuid {
-                            let asset = asset::Entity::find()
-                                .filter(
-                                    asset::Column::Uuid
-                                        .eq(Uuid::from_str(uuid.to_string().as_str())?),
-                                )
-                                .one(db)
-                                .await?;
-
-                            if let Some(asset) = asset {
-                                if asset.client_id != user_client.id as i64 {
-                                    return Err(Error::new(
-                                        "You are not authorized to perform this action",
-                                    ));
-                                }
-                                let new_used_storage_mb =
-                                    client_usage.used_storage_mb - asset.size_mb + size_in_mb;
-                                if new_used_storage_mb > package.storage_capacity_mb {
-                                    return Err(
-                                        Error::new(format!("Insuficient storage: Uploading file of {}mb will exceed your maximum storage of {}mb.", size_in_mb, package.storage_capacity_mb))
-                                    );
-                                }
+                        let mut content = file_value.content;
+                        let sniffed = sniff_content_type(
+                            state,
+                            file_value.content_type.as_deref(),
+                            &mut content,
+                        )?;
+                        policy.check_content_type(&sniffed.detected)?;
+                        if folder.encrypted {
+                            state.key_provider()?;
+                        }
+                        let PreparedContent {
+                            content,
+                            thumbnails,
+                            metadata,
+                        } = prepare_content(
+                            content,
+                            &sniffed.detected,
+                            !folder.encrypted,
+                            input.strip_gps.unwrap_or(false),
+                        )
+                        .await?;
 
-                                Pinata::unpin_file(&asset.ipfs_hash).await?;
-                                Contract::burn_nft(format!("{}x{}", asset.nft_id, asset.folder_id))
-                                    .await?;
-
-                                let pin_result = Pinata::pin_file(file_value.content).await?;
-                                let result = Contract::mint_nft(
-                                    folder.id as u64,
-                                    &asset.uuid.to_string(),
-                                    &pin_result.ipfs_hash,
-                                )
-                                .await?;
-
-                                if let MintNFTResult::Ok(res) = result {
-                                    let mut asset: asset::ActiveModel = asset.into();
-                                    asset.nft_id = Set(res.1.id as i64);
-                                    asset.size_mb = Set(size_in_mb);
-                                    asset.ipfs_hash = Set(pin_result.ipfs_hash);
-                                    asset.folder_id = Set(folder.id.into());
-                                    asset.name = Set(input.name);
-                                    asset.description = Set(input.description);
-                                    asset.last_updated = Set(Utc::now().naive_utc());
-                                    if let Some(content_type) = file_value.content_type {
-                                        asset.content_type = Set(content_type)
-                                    } else {
-                                        return Err(Error::new("Failed to identify content_type"));
-                                    }
+                        Pinata::unpin_file(state, &asset.ipfs_hash).await?;
+                        delete_thumbnails(db, state, ThumbnailOwner::Asset(asset.id)).await?;
+                        Contract::burn_nft(state, TokenId::of(&asset)).await?;
 
-                                    let asset = asset.update(db).await?;
-                                    Ok(asset.into())
-                                } else if let MintNFTResult::Err(err) = result {
-                                    return Err(Error::new(format!("Contract error: {}", err)));
-                                } else {
-                                    return Err(Error::new("Failed to mint nft"));
-                                }
-                            } else {
-                                Err(Error::new(format!(
-                                    "Entity with uuid {} was not found",
-                                    &uuid.to_string()
-                                )))
-                            }
-                        } else {
-                            let new_used_storage_mb = client_usage.used_storage_mb + size_in_mb;
-                            if new_used_storage_mb > package.storage_capacity_mb {
-                                return Err(
-                                    Error::new(format!("Insuficient storage: Uploading file of {}mb will exceed your maximum storage of {}mb.", size_in_mb, package.storage_capacity_mb))
-                                );
-                            }
+                        let size_bytes = content.metadata()?.len();
+                        let stored =
+                            store_content(db, state, &folder, &asset.uuid, content).await?;
+                        let result = Contract::mint_nft(
+                            state,
+                            folder.id as u64,
+                            &asset.uuid.to_string(),
+                            &stored.ipfs_hash,
+                            stored.plaintext_sha256.as_deref(),
+                            owner,
+                        )
+                        .await?;
 
-                            let uuid = Uuid::new_v4();
-                            let pinata_res = Pinata::pin_file(file_value.content).await?;
-                            let result = Contract::mint_nft(
-                                folder.id as u64,
-                                &uuid.to_string(),
-                                &pinata_res.ipfs_hash,
-                            )
-                            .await?;
-
-                            if let MintNFTResult::Ok(res) = result {
-                                if let Some(content_type) = file_value.content_type {
-                                    let new_asset = asset::ActiveModel {
-                                        uuid: Set(uuid),
-                                        name: Set(input.name),
-                                        description: Set(input.description),
-                                        folder_id: Set(folder.id.into()),
-                                        nft_id: Set(res.1.id as i64),
-                                        client_id: Set(user_client.id as i64),
-                                        ipfs_hash: Set(pinata_res.ipfs_hash),
-                                        size_mb: Set(size_in_mb),
-                                        content_type: Set(content_type),
-                                        ..Default::default()
-                                    };
-                                    let new_asset = new_asset.insert(db).await?;
-                                    Ok(new_asset.into())
-                                } else {
-                                    Err(Error::new("Failed to identify content_type"))
-                                }
-                            } else if let MintNFTResult::Err(err) = result {
-                                return Err(Error::new(format!("Contract error: {}", err)));
-                            } else {
-                                return Err(Error::new("Failed to mint nft"));
-                            }
+                        if let MintNFTResult::Ok(res) = result {
+                            let mut asset: asset::ActiveModel = asset.into();
+                            asset.nft_id = Set(res.1.id as i64);
+                            asset.mint_txn_id = Set(Some(res.0.to_string()));
+                            asset.owner_principal = Set(Some(res.1.owner.to_text()));
+                            asset.minted_at = Set(Utc::now().naive_utc());
+                            asset.size_mb = Set(size_in_mb);
+                            asset.metadata = Set(metadata);
+                            asset.ipfs_hash = Set(stored.ipfs_hash);
+                            asset.encrypted_data_key = Set(stored.encrypted_data_key);
+                            asset.encryption_key_id = Set(stored.encryption_key_id);
+                            asset.plaintext_sha256 = Set(stored.plaintext_sha256);
+                            asset.folder_id = Set(folder.id.into());
+                            asset.name = Set(input.name);
+                            asset.description = Set(input.description);
+                            asset.last_updated = Set(Utc::now().naive_utc());
+                            asset.content_type = Set(sniffed.content_type);
+                            asset.detected_content_type = Set(Some(sniffed.detected));
+
+                            let asset = asset.update(db).await?;
+                            record_upload(&asset.content_type, size_bytes);
+                            save_thumbnails(db, state, ThumbnailOwner::Asset(asset.id), thumbnails)
+                                .await;
+                            Ok(asset.into())
+                        } else if let MintNFTResult::Err(err) = result {
+                            return Err(Error::new(format!("Contract error: {}", err)));
+                        } else {
+                            return Err(Error::new("Failed to mint nft"));
                         }
                     } else {
-                        Err(Error::new(
-                            "You do not currently have an active subscription",
-                        ))
+                        Err(Error::new(format!(
+                            "Entity with uuid {} was not found",
+                            &uuid.to_string()
+                        )))
                     }
                 } else {
-                    Err(Error::new("You are not authorized to perform this action"))
+                    quota.check_storage(size_in_mb, 0.0)?;
+                    policy.check_folder_assets(db, &folder, 1).await?;
+                    let owner = mint_owner(
+                        user,
+                        &folder,
+                        input.recipient_principal.as_deref(),
+                        input.mint_to_wallet,
+                    )?;
+
+                    let new_asset = create_asset(
+                        db,
+                        state,
+                        &folder,
+                        user_client.id,
+                        &policy,
+                        PendingAsset {
+                            name: input.name,
+                            description: input.description,
+                            content_type: file_value.content_type,
+                            size_mb: size_in_mb,
+                            content: file_value.content,
+                            strip_gps: input.strip_gps.unwrap_or(false),
+                            owner,
+                        },
+                    )
+                    .await?;
+                    Ok(new_asset.into())
                 }
             } else {
                 Err(Error::new(
@@ -309,4 +324,392 @@ impl AssetMutations {
             )))
         }
     }
+
+    /// Uploads several files into one folder. The storage quota is checked once
+    /// for the whole batch, files are then processed concurrently and every
+    /// file gets its own result so one bad file does not fail the others.
+    async fn create_assets_batch<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: BatchAssetInput,
+    ) -> Result<Vec<BatchAssetResultType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if input.items.is_empty() {
+            return Err(Error::new("Please provide at least one file"));
+        }
+        if input.items.len() > MAX_BATCH_UPLOAD_FILES {
+            return Err(Error::new(format!(
+                "A batch can contain at most {} files",
+                MAX_BATCH_UPLOAD_FILES
+            )));
+        }
+
+        if let Some(user) = user {
+            let folder = folder::Entity::find()
+                .filter(folder::Column::Uuid.eq(Uuid::from_str(input.folder_uuid.as_str())?))
+                .one(db)
+                .await?;
+
+            if let Some(folder) = folder {
+                let quota = ClientQuota::for_user(user, db).await?;
+                if folder.client_id != quota.client.id as i64 {
+                    return Err(Error::new("You are not authorized to perform this action"));
+                }
+                ctx.data::<Arc<RateLimiter>>()?
+                    .check_upload(ctx, quota.client.id)
+                    .await?;
+
+                let mut pending = Vec::with_capacity(input.items.len());
+                let mut total_size_mb = 0.0;
+                for item in input.items {
+                    let file_value = item.file.value(ctx)?;
+                    let size_mb = bytes_to_mb(file_value.size()?);
+                    // A bad recipient only fails its own file
+                    let owner = mint_owner(
+                        user,
+                        &folder,
+                        item.recipient_principal.as_deref(),
+                        input.mint_to_wallet,
+                    );
+                    match owner {
+                        Ok(owner) => {
+                            total_size_mb += size_mb;
+                            pending.push(Ok(PendingAsset {
+                                name: item.name,
+                                description: item.description,
+                                content_type: file_value.content_type,
+                                size_mb,
+                                content: file_value.content,
+                                strip_gps: input.strip_gps.unwrap_or(false),
+                                owner,
+                            }));
+                        }
+                        Err(err) => pending.push(Err((item.name, err))),
+                    }
+                }
+                quota.check_storage(total_size_mb, 0.0)?;
+                let policy = &quota.policy()?;
+                let accepted = pending.iter().filter(|item| item.is_ok()).count();
+                policy
+                    .check_folder_assets(db, &folder, accepted as u64)
+                    .await?;
+
+                let client_id = quota.client.id;
+                let folder = &folder;
+                let results = stream::iter(pending.into_iter().enumerate())
+                    .map(|(index, item)| async move {
+                        let (name, result) = match item {
+                            Ok(item) => (
+                                item.name.clone(),
+                                create_asset(db, state, folder, client_id, policy, item).await,
+                            ),
+                            Err((name, err)) => (name, Err(err)),
+                        };
+                        match result {
+                            Ok(asset) => BatchAssetResultType {
+                                index: index as i32,
+                                name,
+                                asset: Some(asset.into()),
+                                error: None,
+                                error_code: None,
+                            },
+                            Err(err) => BatchAssetResultType {
+                                index: index as i32,
+                                name,
+                                asset: None,
+                                error_code: error_code(&err),
+                                error: Some(err.message),
+                            },
+                        }
+                    })
+                    .buffered(BATCH_UPLOAD_CONCURRENCY)
+                    .collect::<Vec<_>>()
+                    .await;
+
+                Ok(results)
+            } else {
+                Err(Error::new(format!(
+                    "Folder with uuid {} was not found",
+                    input.folder_uuid
+                )))
+            }
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+
+    /// Imports every file of a ZIP archive as an asset of the folder. An
+    /// optional `manifest.csv` or `manifest.json` at the root of the archive
+    /// maps file paths to asset names and descriptions.
+    async fn import_assets_archive<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: AssetArchiveImportInput,
+    ) -> Result<AssetArchiveImportType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if let Some(user) = user {
+            let folder = folder::Entity::find()
+                .filter(folder::Column::Uuid.eq(Uuid::from_str(input.folder_uuid.as_str())?))
+                .one(db)
+                .await?;
+
+            if let Some(folder) = folder {
+                let quota = ClientQuota::for_user(user, db).await?;
+                if folder.client_id != quota.client.id as i64 {
+                    return Err(Error::new("You are not authorized to perform this action"));
+                }
+
+                let archive_value = input.archive.value(ctx)?;
+                let mut archive =
+                    AssetArchive::open(archive_value.content, MAX_BATCH_UPLOAD_FILES)?;
+                quota.check_storage(archive.total_size_mb(), 0.0)?;
+                ctx.data::<Arc<RateLimiter>>()?
+                    .check_upload(ctx, quota.client.id)
+                    .await?;
+
+                let mut skipped: Vec<SkippedArchiveEntryType> = archive
+                    .skipped
+                    .drain(..)
+                    .map(|entry| SkippedArchiveEntryType {
+                        path: entry.path,
+                        reason: entry.reason,
+                    })
+                    .collect();
+
+                let policy = &quota.policy()?;
+                let folder_description = format!("Imported from {}", archive_value.filename);
+                let mut folders: HashMap<String, folder::Model> = HashMap::new();
+                let mut created_folders = Vec::new();
+                let mut planned: HashMap<i32, u64> = HashMap::new();
+                let mut pending = Vec::new();
+                let strip_gps = input.strip_gps.unwrap_or(false);
+                let entries = std::mem::take(&mut archive.entries);
+                for entry in entries {
+                    // Extracting first keeps folders of skipped entries from being created
+                    let extracted = match archive.extract(&entry, strip_gps) {
+                        Ok(asset) => asset,
+                        Err(err) => {
+                            skipped.push(SkippedArchiveEntryType {
+                                path: entry.path,
+                                reason: err.message,
+                            });
+                            continue;
+                        }
+                    };
+
+                    let mut target = folder.clone();
+                    let mut skip_reason = None;
+                    if input.mirror_folders.unwrap_or(false) {
+                        if let Some(directory) = &entry.directory {
+                            let mut key = String::new();
+                            for segment in directory.split('/') {
+                                if !key.is_empty() {
+                                    key.push('/');
+                                }
+                                key.push_str(segment);
+                                target = match folders.get(&key) {
+                                    Some(child) => child.clone(),
+                                    None => {
+                                        let (child, created) = match find_or_create_child_folder(
+                                            db,
+                                            state,
+                                            policy,
+                                            &target,
+                                            segment,
+                                            &folder_description,
+                                        )
+                                        .await
+                                        {
+                                            Ok(result) => result,
+                                            Err(err) => {
+                                                skip_reason = Some(err.message);
+                                                break;
+                                            }
+                                        };
+                                        if created {
+                                            created_folders.push(child.clone());
+                                        }
+                                        folders.insert(key.clone(), child.clone());
+                                        child
+                                    }
+                                };
+                            }
+                        }
+                    }
+
+                    let added = planned.get(&target.id).copied().unwrap_or(0) + 1;
+                    if skip_reason.is_none() {
+                        if let Err(err) = policy.check_folder_assets(db, &target, added).await {
+                            skip_reason = Some(err.message);
+                        }
+                    }
+                    let extracted = match skip_reason {
+                        Some(reason) => Err(Error::new(reason)),
+                        None => mint_owner(user, &target, None, input.mint_to_wallet)
+                            .map(|owner| PendingAsset { owner, ..extracted }),
+                    };
+                    match extracted {
+                        Ok(asset) => {
+                            planned.insert(target.id, added);
+                            pending.push((entry.path, target, asset));
+                        }
+                        Err(err) => skipped.push(SkippedArchiveEntryType {
+                            path: entry.path,
+                            reason: err.message,
+                        }),
+                    }
+                }
+
+                let client_id = quota.client.id;
+                let results = stream::iter(pending)
+                    .map(|(path, target, item)| async move {
+                        (
+                            path,
+                            create_asset(db, state, &target, client_id, policy, item).await,
+                        )
+                    })
+                    .buffered(BATCH_UPLOAD_CONCURRENCY)
+                    .collect::<Vec<_>>()
+                    .await;
+
+                let mut imported = Vec::new();
+                for (path, result) in results {
+                    match result {
+                        Ok(asset) => imported.push(asset.into()),
+                        Err(err) => skipped.push(SkippedArchiveEntryType {
+                            path,
+                            reason: err.message,
+                        }),
+                    }
+                }
+
+                Ok(AssetArchiveImportType {
+                    imported,
+                    folders: created_folders
+                        .into_iter()
+                        .map(|item| item.into())
+                        .collect(),
+                    skipped,
+                })
+            } else {
+                Err(Error::new(format!(
+                    "Folder with uuid {} was not found",
+                    input.folder_uuid
+                )))
+            }
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+
+    /// Moves the asset's NFT from the backend's identity to the recipient's
+    /// wallet. Once transferred the asset can no longer be replaced.
+    async fn transfer_asset<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: AssetTransferInput,
+    ) -> Result<AssetTransferType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if let Some(user) = user {
+            let client = client::Entity::find()
+                .filter(client::Column::UserId.eq(user.id))
+                .one(db)
+                .await?;
+            let client = match client {
+                Some(client) => client,
+                None => {
+                    return Err(Error::new(
+                        "You do not currently have an active subscription",
+                    ))
+                }
+            };
+            record_client(client.id);
+
+            let asset = asset::Entity::find()
+                .filter(asset::Column::Uuid.eq(Uuid::from_str(input.asset_uuid.as_str())?))
+                .one(db)
+                .await?;
+
+            if let Some(asset) = asset {
+                if asset.client_id != client.id as i64 {
+                    return Err(Error::new("You are not authorized to perform this action"));
+                }
+
+                if !held_by_backend(state, &asset)? {
+                    return Err(Error::new(
+                        "Only assets held by the backend can be transferred",
+                    ));
+                }
+                let recipient = parse_principal(&input.recipient_principal)?;
+                if asset.owner_principal.as_deref() == Some(recipient.to_text().as_str()) {
+                    return Err(Error::new("The recipient already owns this asset"));
+                }
+
+                let result = Contract::transfer_nft(state, TokenId::of(&asset), recipient).await?;
+
+                match result {
+                    TransferNFTResult::Ok(txn_id) => {
+                        let transfer = asset_transfer::ActiveModel {
+                            uuid: Set(Uuid::new_v4()),
+                            asset_id: Set(asset.id as i64),
+                            from_principal: Set(asset.owner_principal.clone()),
+                            to_principal: Set(recipient.to_text()),
+                            txn_id: Set(txn_id.to_string()),
+                            ..Default::default()
+                        };
+                        let transfer = transfer.insert(db).await?;
+
+                        let mut asset: asset::ActiveModel = asset.into();
+                        asset.owner_principal = Set(Some(recipient.to_text()));
+                        asset.last_updated = Set(Utc::now().naive_utc());
+                        asset.update(db).await?;
+
+                        Ok(transfer.into())
+                    }
+                    TransferNFTResult::Err(err) => {
+                        Err(Error::new(format!("Contract error: {}", err)))
+                    }
+                }
+            } else {
+                Err(Error::new(format!(
+                    "Asset with uuid {} was not found",
+                    *input.asset_uuid
+                )))
+            }
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+}
+
+/// Whether the asset's NFT is still owned by the backend, assets minted
+/// before owners were recorded always are
+fn held_by_backend(state: &AppState, asset: &asset::Model) -> Result<bool> {
+    if let Some(owner) = &asset.owner_principal {
+        Ok(*owner == Contract::principal(state)?.to_text())
+    } else {
+        Ok(true)
+    }
+}
+
+fn error_code(err: &Error) -> Option<String> {
+    match err.extensions.as_ref()?.get("code")? {
+        async_graphql::Value::String(code) => Some(code.clone()),
+        _ => None,
+    }
 }
@@ -0,0 +1,86 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_graphql::*;
+use entity::entities::{client, export_job, folder, user};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use crate::{
+    apps::assets::{
+        graphql::types::outputs::exports::ExportJobType,
+        utils::exports::{run_export_job, ExportJobStatus},
+    },
+    config::{state::AppState, telemetry::record_client},
+};
+
+#[derive(Default)]
+pub struct ExportMutations;
+
+#[Object]
+impl ExportMutations {
+    /// Starts building a ZIP of the folder's assets, or of every asset of the
+    /// client when no folder is given. Poll the job for its `downloadUrl`.
+    async fn create_export_job<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        folder_uuid: Option<ID>,
+    ) -> Result<ExportJobType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if let Some(user) = user {
+            let client = client::Entity::find()
+                .filter(client::Column::UserId.eq(user.id))
+                .one(db)
+                .await?;
+
+            if let Some(client) = client {
+                record_client(client.id);
+                let folder_id = if let Some(uuid) = folder_uuid {
+                    let folder = folder::Entity::find()
+                        .filter(folder::Column::Uuid.eq(Uuid::from_str(uuid.as_str())?))
+                        .one(db)
+                        .await?;
+                    match folder {
+                        Some(folder) if folder.client_id == client.id as i64 => {
+                            Some(folder.id as i64)
+                        }
+                        Some(_) => {
+                            return Err(Error::new("You are not authorized to perform this action"))
+                        }
+                        None => {
+                            return Err(Error::new(format!(
+                                "Folder with uuid {} was not found",
+                                *uuid
+                            )))
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                let job = export_job::ActiveModel {
+                    uuid: Set(Uuid::new_v4()),
+                    client_id: Set(client.id as i64),
+                    folder_id: Set(folder_id),
+                    status: Set(ExportJobStatus::Pending.as_str().to_string()),
+                    asset_count: Set(0),
+                    ..Default::default()
+                };
+                let job = job.insert(db).await?;
+                actix_web::rt::spawn(run_export_job(db.clone(), state.clone(), job.id));
+
+                Ok(job.into())
+            } else {
+                Err(Error::new(
+                    "You do not currently have an active subscription",
+                ))
+            }
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+}
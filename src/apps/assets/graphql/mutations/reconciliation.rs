@@ -0,0 +1,93 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_graphql::*;
+use entity::entities::{client, reconciliation_issue, reconciliation_job, user};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use crate::{
+    apps::{
+        assets::{
+            graphql::types::outputs::reconciliation::{
+                ReconciliationIssueType, ReconciliationJobType,
+            },
+            utils::reconciliation::{
+                repair_issue, run_reconciliation_job, ReconciliationJobStatus, RepairAction,
+            },
+        },
+        users::utils::auth::admin_user,
+    },
+    config::state::AppState,
+};
+
+#[derive(Default)]
+pub struct ReconciliationMutations;
+
+#[Object]
+impl ReconciliationMutations {
+    /// Starts comparing assets with the tokens on the canister, for one
+    /// client or for everyone. Poll the job for its issues.
+    async fn create_reconciliation_job<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        client_uuid: Option<ID>,
+    ) -> Result<ReconciliationJobType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        admin_user(ctx.data::<Option<user::Model>>()?)?;
+
+        let client_id = if let Some(uuid) = client_uuid {
+            let client = client::Entity::find()
+                .filter(client::Column::Uuid.eq(Uuid::from_str(uuid.as_str())?))
+                .one(db)
+                .await?;
+            match client {
+                Some(client) => Some(client.id as i64),
+                None => {
+                    return Err(Error::new(format!(
+                        "Client with uuid {} was not found",
+                        *uuid
+                    )))
+                }
+            }
+        } else {
+            None
+        };
+
+        let job = reconciliation_job::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            client_id: Set(client_id),
+            status: Set(ReconciliationJobStatus::Pending.as_str().to_string()),
+            ..Default::default()
+        };
+        let job = job.insert(db).await?;
+        actix_web::rt::spawn(run_reconciliation_job(db.clone(), state.clone(), job.id));
+
+        Ok(job.into())
+    }
+
+    async fn repair_reconciliation_issue<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uuid: ID,
+        action: RepairAction,
+    ) -> Result<ReconciliationIssueType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        admin_user(ctx.data::<Option<user::Model>>()?)?;
+
+        let issue = reconciliation_issue::Entity::find()
+            .filter(reconciliation_issue::Column::Uuid.eq(Uuid::from_str(uuid.as_str())?))
+            .one(db)
+            .await?;
+        if let Some(issue) = issue {
+            let issue = repair_issue(db, state, issue, action).await?;
+            Ok(issue.into())
+        } else {
+            Err(Error::new(format!(
+                "Reconciliation issue with uuid {} was not found",
+                *uuid
+            )))
+        }
+    }
+}
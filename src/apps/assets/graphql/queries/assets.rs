@@ -3,18 +3,72 @@ use std::str::FromStr;
 use async_graphql::*;
 use entity::entities::{asset, client, folder, user};
 use sea_orm::{
-    entity::*, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    entity::*, sea_query::Expr, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, Select,
 };
 use uuid::Uuid;
 
 use crate::apps::{
     assets::graphql::types::{
-        inputs::assets::{AssetQueryOptions, FolderQueryOptions},
+        inputs::assets::{AssetFilter, AssetQueryOptions, FolderQueryOptions},
         outputs::assets::{AssetType, FolderType},
     },
     common::graphql::types::inputs::Paginated,
 };
 
+/// Applies an `AssetFilter`. The metadata bounds are inclusive and only
+/// match assets whose extracted metadata has the field.
+fn filter_assets(mut stmt: Select<asset::Entity>, filter: AssetFilter) -> Select<asset::Entity> {
+    if let Some(name) = filter.name {
+        stmt = stmt.filter(asset::Column::Name.contains(name));
+    }
+    if let Some(description) = filter.description {
+        stmt = stmt.filter(asset::Column::Description.contains(description));
+    }
+    if let Some(min_size_mb) = filter.min_size_mb {
+        stmt = stmt.filter(asset::Column::SizeMb.gt(min_size_mb));
+    }
+    if let Some(max_size_mb) = filter.max_size_mb {
+        stmt = stmt.filter(asset::Column::SizeMb.lt(max_size_mb));
+    }
+
+    let bounds = [
+        ("width", ">=", filter.min_width.map(f64::from)),
+        ("width", "<=", filter.max_width.map(f64::from)),
+        ("height", ">=", filter.min_height.map(f64::from)),
+        ("height", "<=", filter.max_height.map(f64::from)),
+        ("duration_seconds", ">=", filter.min_duration_seconds),
+        ("duration_seconds", "<=", filter.max_duration_seconds),
+        ("page_count", ">=", filter.min_page_count.map(f64::from)),
+        ("page_count", "<=", filter.max_page_count.map(f64::from)),
+    ];
+    for (key, operator, value) in bounds {
+        if let Some(value) = value {
+            stmt = stmt.filter(Expr::cust_with_values(
+                format!(
+                    r#"("asset"."metadata"->>'{}')::float8 {} $1"#,
+                    key, operator
+                ),
+                [value],
+            ));
+        }
+    }
+
+    if let Some(codec) = filter.codec {
+        stmt = stmt.filter(Expr::cust_with_values(
+            r#""asset"."metadata"->'codecs' @> jsonb_build_array($1::text)"#,
+            [codec.to_lowercase()],
+        ));
+    }
+    if let Some(has_gps) = filter.has_gps {
+        stmt = stmt.filter(Expr::cust_with_values(
+            r#"COALESCE("asset"."metadata"->'exif' @> '[{"tag": "GPSLatitude"}]', false) = $1"#,
+            [has_gps],
+        ));
+    }
+    stmt
+}
+
 #[derive(Default)]
 pub struct AssetQueries;
 
@@ -46,18 +100,7 @@ impl AssetQueries {
                 stmt = stmt.limit(opts.limit);
                 if let Some(opts) = opts.opts {
                     if let Some(filter) = opts.filter {
-                        if let Some(name) = filter.name {
-                            stmt = stmt.filter(asset::Column::Name.contains(name));
-                        }
-                        if let Some(description) = filter.description {
-                            stmt = stmt.filter(asset::Column::Description.contains(description));
-                        }
-                        if let Some(min_size_mb) = filter.min_size_mb {
-                            stmt = stmt.filter(asset::Column::SizeMb.gt(min_size_mb));
-                        }
-                        if let Some(max_size_mb) = filter.max_size_mb {
-                            stmt = stmt.filter(asset::Column::SizeMb.lt(max_size_mb));
-                        }
+                        stmt = filter_assets(stmt, filter);
                     }
                     if let Some(ordering) = opts.ordering {
                         if let Some(date_added) = ordering.date_added {
@@ -179,18 +222,7 @@ impl AssetQueries {
                 stmt = stmt.limit(opts.limit);
                 if let Some(opts) = opts.opts {
                     if let Some(filter) = opts.filter {
-                        if let Some(name) = filter.name {
-                            stmt = stmt.filter(asset::Column::Name.contains(name));
-                        }
-                        if let Some(description) = filter.description {
-                            stmt = stmt.filter(asset::Column::Description.contains(description));
-                        }
-                        if let Some(min_size_mb) = filter.min_size_mb {
-                            stmt = stmt.filter(asset::Column::SizeMb.gt(min_size_mb));
-                        }
-                        if let Some(max_size_mb) = filter.max_size_mb {
-                            stmt = stmt.filter(asset::Column::SizeMb.lt(max_size_mb));
-                        }
+                        stmt = filter_assets(stmt, filter);
                     }
                     if let Some(ordering) = opts.ordering {
                         if let Some(date_added) = ordering.date_added {
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use async_graphql::*;
+use entity::entities::{client, export_job, user};
+use sea_orm::{
+    entity::*, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+};
+use uuid::Uuid;
+
+use crate::apps::assets::graphql::types::outputs::exports::ExportJobType;
+
+#[derive(Default)]
+pub struct ExportQueries;
+
+#[Object]
+impl ExportQueries {
+    async fn export_job<'ctx>(&self, ctx: &Context<'ctx>, uuid: ID) -> Result<ExportJobType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+        if let Some(user) = user {
+            let job = export_job::Entity::find()
+                .join(JoinType::InnerJoin, export_job::Relation::Client.def())
+                .filter(client::Column::UserId.eq(user.id))
+                .filter(export_job::Column::Uuid.eq(Uuid::from_str(uuid.as_str())?))
+                .one(db)
+                .await?;
+
+            if let Some(job) = job {
+                Ok(job.into())
+            } else {
+                Err(Error::new(format!(
+                    "Export job with uuid {} was not found",
+                    *uuid
+                )))
+            }
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+
+    async fn client_export_jobs<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ExportJobType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+        if let Some(user) = user {
+            let jobs = export_job::Entity::find()
+                .join(JoinType::InnerJoin, export_job::Relation::Client.def())
+                .filter(client::Column::UserId.eq(user.id))
+                .order_by_desc(export_job::Column::DateAdded)
+                .all(db)
+                .await?;
+            Ok(jobs.into_iter().map(|item| item.into()).collect())
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+}
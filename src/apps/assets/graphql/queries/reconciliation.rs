@@ -0,0 +1,53 @@
+use std::str::FromStr;
+
+use async_graphql::*;
+use entity::entities::{reconciliation_job, user};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use uuid::Uuid;
+
+use crate::apps::{
+    assets::graphql::types::outputs::reconciliation::ReconciliationJobType,
+    users::utils::auth::admin_user,
+};
+
+#[derive(Default)]
+pub struct ReconciliationQueries;
+
+#[Object]
+impl ReconciliationQueries {
+    async fn reconciliation_job<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        uuid: ID,
+    ) -> Result<ReconciliationJobType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        admin_user(ctx.data::<Option<user::Model>>()?)?;
+
+        let job = reconciliation_job::Entity::find()
+            .filter(reconciliation_job::Column::Uuid.eq(Uuid::from_str(uuid.as_str())?))
+            .one(db)
+            .await?;
+        if let Some(job) = job {
+            Ok(job.into())
+        } else {
+            Err(Error::new(format!(
+                "Reconciliation job with uuid {} was not found",
+                *uuid
+            )))
+        }
+    }
+
+    async fn reconciliation_jobs<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<Vec<ReconciliationJobType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        admin_user(ctx.data::<Option<user::Model>>()?)?;
+
+        let jobs = reconciliation_job::Entity::find()
+            .order_by_desc(reconciliation_job::Column::DateAdded)
+            .all(db)
+            .await?;
+        Ok(jobs.into_iter().map(|item| item.into()).collect())
+    }
+}
@@ -7,6 +7,38 @@ pub struct AssetInput {
     pub folder_uuid: String,
     pub description: String,
     pub file: Upload,
+    /// Leave GPS coordinates out of the image's EXIF metadata
+    pub strip_gps: Option<bool>,
+    /// Principal to mint the NFT to instead of the backend
+    pub recipient_principal: Option<String>,
+    /// Mint the NFT to your linked wallet, overriding the folder's setting
+    pub mint_to_wallet: Option<bool>,
+}
+
+#[derive(InputObject)]
+pub struct BatchAssetItemInput {
+    pub name: String,
+    pub description: String,
+    pub file: Upload,
+    pub recipient_principal: Option<String>,
+}
+
+#[derive(InputObject)]
+pub struct BatchAssetInput {
+    pub folder_uuid: String,
+    pub items: Vec<BatchAssetItemInput>,
+    pub strip_gps: Option<bool>,
+    pub mint_to_wallet: Option<bool>,
+}
+
+#[derive(InputObject)]
+pub struct AssetArchiveImportInput {
+    pub folder_uuid: String,
+    pub archive: Upload,
+    /// Create sub folders that mirror the directories inside the archive
+    pub mirror_folders: Option<bool>,
+    pub strip_gps: Option<bool>,
+    pub mint_to_wallet: Option<bool>,
 }
 
 #[derive(InputObject)]
@@ -15,6 +47,18 @@ pub struct FolderInput {
     pub name: String,
     pub description: String,
     pub logo: Upload,
+    /// Encrypt the folder's assets before pinning them. Only applies when
+    /// the folder is created.
+    pub encrypted: Option<bool>,
+    /// Mint the folder's new assets to your linked wallet
+    pub mint_to_wallet: Option<bool>,
+}
+
+#[derive(InputObject)]
+pub struct AssetTransferInput {
+    pub asset_uuid: ID,
+    /// ICP principal that receives the asset's NFT
+    pub recipient_principal: String,
 }
 
 #[derive(InputObject)]
@@ -42,6 +86,18 @@ pub struct AssetFilter {
     pub min_size_mb: Option<f64>,
     pub max_size_mb: Option<f64>,
     pub content_type: Option<String>,
+    pub min_width: Option<i32>,
+    pub max_width: Option<i32>,
+    pub min_height: Option<i32>,
+    pub max_height: Option<i32>,
+    pub min_duration_seconds: Option<f64>,
+    pub max_duration_seconds: Option<f64>,
+    pub min_page_count: Option<i32>,
+    pub max_page_count: Option<i32>,
+    /// Matches assets with a track in this codec, e.g. `aac` or `mp3`
+    pub codec: Option<String>,
+    /// Matches images whose EXIF contains GPS coordinates
+    pub has_gps: Option<bool>,
 }
 
 #[derive(InputObject)]
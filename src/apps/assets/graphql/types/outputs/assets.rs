@@ -1,9 +1,57 @@
+use std::sync::Arc;
+
 use async_graphql::*;
-use entity::entities::{asset, client, folder};
+use entity::entities::{asset, asset_transfer, client, folder, thumbnail};
 use sea_orm::{
-    entity::*, Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
+    entity::*,
+    sea_query::{Expr, Func, SimpleExpr},
+    Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use crate::{
+    apps::assets::utils::{
+        certificates::certificate_pdf_path,
+        chain::{cached_collection, cached_nft},
+        contract::{NFTCollectionDetails, NFTDetails, TokenId},
+        metadata::AssetMetadata,
+        pinata::Pinata,
+    },
+    config::state::AppState,
 };
 
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ThumbnailType {
+    pub size: i32,
+    pub width: i32,
+    pub height: i32,
+    pub content_type: String,
+
+    #[graphql(skip)]
+    pub ipfs_hash: String,
+}
+
+impl From<thumbnail::Model> for ThumbnailType {
+    fn from(value: thumbnail::Model) -> Self {
+        Self {
+            size: value.size,
+            width: value.width,
+            height: value.height,
+            content_type: value.content_type,
+            ipfs_hash: value.ipfs_hash,
+        }
+    }
+}
+
+#[ComplexObject]
+impl ThumbnailType {
+    async fn url<'ctx>(&self, ctx: &Context<'ctx>) -> Result<String> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        Ok(Pinata::build_url(state, self.ipfs_hash.clone()))
+    }
+}
+
 #[derive(SimpleObject)]
 #[graphql(complex)]
 pub struct FolderType {
@@ -12,10 +60,19 @@ pub struct FolderType {
     pub name: String,
     pub description: String,
     pub logo_hash: String,
+    pub qr_code_url: String,
+    pub encrypted: bool,
+    /// New assets are minted to the uploader's linked wallet
+    pub mint_to_wallet: bool,
+    /// Transaction that created the folder's collection
+    pub create_txn_id: Option<String>,
 
     #[graphql(skip)]
     pub client_id: i64,
 
+    #[graphql(skip)]
+    pub parent_id: Option<i64>,
+
     pub date_added: String,
     pub last_updated: String,
 }
@@ -28,7 +85,12 @@ impl From<folder::Model> for FolderType {
             name: value.name,
             description: value.description,
             logo_hash: value.logo_hash,
+            qr_code_url: format!("/folders/{}/qr.png", value.uuid),
+            encrypted: value.encrypted,
+            mint_to_wallet: value.mint_to_wallet,
+            create_txn_id: value.create_txn_id,
             client_id: value.client_id,
+            parent_id: value.parent_id,
             date_added: value.date_added.to_string(),
             last_updated: value.last_updated.to_string(),
         }
@@ -37,6 +99,27 @@ impl From<folder::Model> for FolderType {
 
 #[ComplexObject]
 impl FolderType {
+    async fn parent<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<FolderType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        if let Some(parent_id) = self.parent_id {
+            let parent = folder::Entity::find_by_id(parent_id as i32).one(db).await?;
+            Ok(parent.map(|item| item.into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn thumbnails<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ThumbnailType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let folder_id = self.id.parse::<i64>()?;
+        let thumbnails = thumbnail::Entity::find()
+            .filter(thumbnail::Column::FolderId.eq(folder_id))
+            .order_by_asc(thumbnail::Column::Size)
+            .all(db)
+            .await?;
+        Ok(thumbnails.into_iter().map(|item| item.into()).collect())
+    }
+
     async fn items_count<'ctx>(&self, ctx: &Context<'ctx>) -> Result<i64> {
         let db = ctx.data::<DatabaseConnection>()?;
         let folder_id = self.id.parse::<i64>()?;
@@ -68,9 +151,19 @@ impl FolderType {
             Ok(0.0)
         }
     }
+
+    /// The folder's collection as the canister currently sees it, `None`
+    /// when it no longer exists
+    async fn on_chain<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<OnChainCollectionType>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let collection_id = self.id.parse::<u64>()?;
+        let collection = cached_collection(state, collection_id).await?;
+        Ok(collection.map(|item| item.into()))
+    }
 }
 
 #[derive(SimpleObject)]
+#[graphql(complex)]
 pub struct AssetType {
     pub id: ID,
     pub uuid: String,
@@ -78,8 +171,24 @@ pub struct AssetType {
     pub description: String,
     pub ipfs_hash: String,
     pub content_type: String,
+    pub detected_content_type: Option<String>,
     pub nft_id: i64,
     pub size_mb: f64,
+    pub certificate_pdf_url: String,
+    pub qr_code_url: String,
+    /// Authenticated download of the original content, decrypted if needed
+    pub download_url: String,
+    pub encrypted: bool,
+    /// Digest of the original content of encrypted assets, whose `ipfs_hash` is the ciphertext's
+    pub plaintext_sha256: Option<String>,
+    pub metadata: Option<AssetMetadata>,
+    /// ICP principal holding the asset's NFT, unknown for assets minted
+    /// before owners were recorded
+    pub owner: Option<String>,
+    /// Transaction that minted the asset's current NFT
+    pub mint_txn_id: Option<String>,
+    /// When the current NFT was minted
+    pub minted_at: String,
 
     #[graphql(skip)]
     pub client_id: i64,
@@ -100,8 +209,18 @@ impl From<asset::Model> for AssetType {
             description: value.description,
             ipfs_hash: value.ipfs_hash,
             content_type: value.content_type,
+            detected_content_type: value.detected_content_type,
             nft_id: value.nft_id,
             size_mb: value.size_mb,
+            certificate_pdf_url: certificate_pdf_path(&value.uuid.to_string()),
+            qr_code_url: format!("/assets/{}/qr.png", value.uuid),
+            download_url: format!("/assets/{}/content", value.uuid),
+            encrypted: value.encrypted_data_key.is_some(),
+            plaintext_sha256: value.plaintext_sha256,
+            metadata: value.metadata.and_then(AssetMetadata::from_json),
+            owner: value.owner_principal,
+            mint_txn_id: value.mint_txn_id,
+            minted_at: value.minted_at.to_string(),
             client_id: value.client_id,
             folder_id: value.folder_id,
             date_added: value.date_added.to_string(),
@@ -110,6 +229,146 @@ impl From<asset::Model> for AssetType {
     }
 }
 
+#[ComplexObject]
+impl AssetType {
+    async fn thumbnails<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ThumbnailType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let asset_id = self.id.parse::<i64>()?;
+        let thumbnails = thumbnail::Entity::find()
+            .filter(thumbnail::Column::AssetId.eq(asset_id))
+            .order_by_asc(thumbnail::Column::Size)
+            .all(db)
+            .await?;
+        Ok(thumbnails.into_iter().map(|item| item.into()).collect())
+    }
+
+    /// Transfers of the asset's NFT, oldest first
+    async fn transfers<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<AssetTransferType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let asset_id = self.id.parse::<i64>()?;
+        let transfers = asset_transfer::Entity::find()
+            .filter(asset_transfer::Column::AssetId.eq(asset_id))
+            .order_by_asc(asset_transfer::Column::DateAdded)
+            .all(db)
+            .await?;
+        Ok(transfers.into_iter().map(|item| item.into()).collect())
+    }
+
+    /// The asset's NFT as the canister currently sees it, `None` when it
+    /// no longer exists
+    async fn on_chain<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<OnChainNFTType>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let token_id = TokenId::new(self.nft_id as u64, self.folder_id as u64);
+        let nft = cached_nft(state, token_id).await?;
+        Ok(nft.map(|item| item.into()))
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct OnChainNFTType {
+    pub token_id: String,
+    pub owner: String,
+    pub collection_id: String,
+    /// Raw metadata stored with the token
+    pub metadata: String,
+    /// Content the token was minted for, when its metadata is an asset's
+    pub ipfs_hash: Option<String>,
+    pub plaintext_sha256: Option<String>,
+}
+
+impl From<NFTDetails> for OnChainNFTType {
+    fn from(value: NFTDetails) -> Self {
+        let asset = value.asset();
+        Self {
+            token_id: value.token_id().to_string(),
+            owner: value.owner.to_text(),
+            collection_id: value.collection_id.to_string(),
+            metadata: value.metadata,
+            ipfs_hash: asset.as_ref().map(|asset| asset.ipfs_hash.clone()),
+            plaintext_sha256: asset.and_then(|asset| asset.plaintext_sha256),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct OnChainCollectionType {
+    pub id: String,
+    pub owner: String,
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    pub logo: Option<String>,
+}
+
+impl From<NFTCollectionDetails> for OnChainCollectionType {
+    fn from(value: NFTCollectionDetails) -> Self {
+        Self {
+            id: value.id.to_string(),
+            owner: value.owner.to_text(),
+            name: value.name,
+            symbol: value.symbol,
+            description: value.description,
+            logo: value.logo,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct AssetTransferType {
+    pub id: ID,
+    pub uuid: String,
+    pub from_principal: Option<String>,
+    pub to_principal: String,
+    pub txn_id: String,
+    pub date_added: String,
+}
+
+impl From<asset_transfer::Model> for AssetTransferType {
+    fn from(value: asset_transfer::Model) -> Self {
+        Self {
+            id: value.id.into(),
+            uuid: value.uuid.to_string(),
+            from_principal: value.from_principal,
+            to_principal: value.to_principal,
+            txn_id: value.txn_id,
+            date_added: value.date_added.to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct BatchAssetResultType {
+    pub index: i32,
+    pub name: String,
+    pub asset: Option<AssetType>,
+    pub error: Option<String>,
+    /// The `code` extension of the error, e.g. `FILE_TOO_LARGE`
+    pub error_code: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct SkippedArchiveEntryType {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(SimpleObject)]
+pub struct AssetArchiveImportType {
+    pub imported: Vec<AssetType>,
+    pub folders: Vec<FolderType>,
+    pub skipped: Vec<SkippedArchiveEntryType>,
+}
+
+/// The sniffed content type, falling back to the declared one for assets
+/// uploaded before uploads were sniffed
+fn stored_content_type() -> SimpleExpr {
+    Func::coalesce([
+        Expr::col((asset::Entity, asset::Column::DetectedContentType)).into(),
+        Expr::col((asset::Entity, asset::Column::ContentType)).into(),
+    ])
+    .into()
+}
+
 #[derive(SimpleObject)]
 pub struct StorageSummary {
     pub count: i64,
@@ -127,7 +386,7 @@ impl UserFileStorageSummary {
     async fn images<'ctx>(&self, ctx: &Context<'ctx>) -> Result<StorageSummary> {
         let db = ctx.data::<DatabaseConnection>()?;
         let mut stmt =
-            asset::Entity::find().filter(asset::Column::ContentType.starts_with("image"));
+            asset::Entity::find().filter(Expr::expr(stored_content_type()).like("image%"));
 
         if let Some(id) = self.client_id {
             stmt = stmt
@@ -153,7 +412,7 @@ impl UserFileStorageSummary {
     async fn videos<'ctx>(&self, ctx: &Context<'ctx>) -> Result<StorageSummary> {
         let db = ctx.data::<DatabaseConnection>()?;
         let mut stmt =
-            asset::Entity::find().filter(asset::Column::ContentType.starts_with("video"));
+            asset::Entity::find().filter(Expr::expr(stored_content_type()).like("video%"));
 
         if let Some(id) = self.client_id {
             stmt = stmt
@@ -179,7 +438,7 @@ impl UserFileStorageSummary {
     async fn audios<'ctx>(&self, ctx: &Context<'ctx>) -> Result<StorageSummary> {
         let db = ctx.data::<DatabaseConnection>()?;
         let mut stmt =
-            asset::Entity::find().filter(asset::Column::ContentType.starts_with("audio"));
+            asset::Entity::find().filter(Expr::expr(stored_content_type()).like("audio%"));
 
         if let Some(id) = self.client_id {
             stmt = stmt
@@ -206,8 +465,8 @@ impl UserFileStorageSummary {
         let db = ctx.data::<DatabaseConnection>()?;
         let mut stmt = asset::Entity::find().filter(
             Condition::any()
-                .add(asset::Column::ContentType.starts_with("application"))
-                .add(asset::Column::ContentType.starts_with("text")),
+                .add(Expr::expr(stored_content_type()).like("application%"))
+                .add(Expr::expr(stored_content_type()).like("text%")),
         );
 
         if let Some(id) = self.client_id {
@@ -235,11 +494,11 @@ impl UserFileStorageSummary {
         let db = ctx.data::<DatabaseConnection>()?;
         let mut stmt = asset::Entity::find().filter(
             Condition::any()
-                .add(asset::Column::ContentType.starts_with("application"))
-                .add(asset::Column::ContentType.starts_with("audio"))
-                .add(asset::Column::ContentType.starts_with("video"))
-                .add(asset::Column::ContentType.starts_with("image"))
-                .add(asset::Column::ContentType.starts_with("text"))
+                .add(Expr::expr(stored_content_type()).like("application%"))
+                .add(Expr::expr(stored_content_type()).like("audio%"))
+                .add(Expr::expr(stored_content_type()).like("video%"))
+                .add(Expr::expr(stored_content_type()).like("image%"))
+                .add(Expr::expr(stored_content_type()).like("text%"))
                 .not(),
         );
 
@@ -0,0 +1,60 @@
+use async_graphql::*;
+use entity::entities::{export_job, folder};
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::apps::assets::{
+    graphql::types::outputs::assets::FolderType, utils::exports::ExportJobStatus,
+};
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ExportJobType {
+    pub id: ID,
+    pub uuid: String,
+
+    #[graphql(skip)]
+    pub folder_id: Option<i64>,
+
+    pub status: ExportJobStatus,
+    pub asset_count: i32,
+    pub error: Option<String>,
+    pub date_added: String,
+    pub completed_at: Option<String>,
+}
+
+impl From<export_job::Model> for ExportJobType {
+    fn from(value: export_job::Model) -> Self {
+        Self {
+            id: value.id.into(),
+            uuid: value.uuid.to_string(),
+            folder_id: value.folder_id,
+            status: value.status.as_str().into(),
+            asset_count: value.asset_count,
+            error: value.error,
+            date_added: value.date_added.to_string(),
+            completed_at: value.completed_at.map(|date| date.to_string()),
+        }
+    }
+}
+
+#[ComplexObject]
+impl ExportJobType {
+    async fn folder<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<FolderType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        if let Some(folder_id) = self.folder_id {
+            let folder = folder::Entity::find_by_id(folder_id as i32).one(db).await?;
+            Ok(folder.map(|item| item.into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Path of the archive download, available once the export has completed
+    async fn download_url(&self) -> Option<String> {
+        if self.status == ExportJobStatus::Completed {
+            Some(format!("/exports/{}", self.uuid))
+        } else {
+            None
+        }
+    }
+}
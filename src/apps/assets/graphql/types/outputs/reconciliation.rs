@@ -0,0 +1,132 @@
+use async_graphql::*;
+use entity::entities::{asset, client, reconciliation_issue, reconciliation_job};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::apps::{
+    assets::{
+        graphql::types::outputs::assets::AssetType,
+        utils::reconciliation::{ReconciliationIssueKind, ReconciliationJobStatus},
+    },
+    users::graphql::types::outputs::clients::ClientType,
+};
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ReconciliationJobType {
+    pub id: ID,
+    pub uuid: String,
+
+    #[graphql(skip)]
+    pub client_id: Option<i64>,
+
+    pub status: ReconciliationJobStatus,
+    pub asset_count: i32,
+    pub consistent_count: i32,
+    pub issue_count: i32,
+    pub error: Option<String>,
+    pub date_added: String,
+    pub completed_at: Option<String>,
+}
+
+impl From<reconciliation_job::Model> for ReconciliationJobType {
+    fn from(value: reconciliation_job::Model) -> Self {
+        Self {
+            id: value.id.into(),
+            uuid: value.uuid.to_string(),
+            client_id: value.client_id,
+            status: value.status.as_str().into(),
+            asset_count: value.asset_count,
+            consistent_count: value.consistent_count,
+            issue_count: value.issue_count,
+            error: value.error,
+            date_added: value.date_added.to_string(),
+            completed_at: value.completed_at.map(|date| date.to_string()),
+        }
+    }
+}
+
+#[ComplexObject]
+impl ReconciliationJobType {
+    /// The client the job was limited to, all clients when empty
+    async fn client<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<ClientType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        if let Some(client_id) = self.client_id {
+            let client = client::Entity::find_by_id(client_id as i32).one(db).await?;
+            Ok(client.map(|item| item.into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn issues<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        kind: Option<ReconciliationIssueKind>,
+        unresolved_only: Option<bool>,
+    ) -> Result<Vec<ReconciliationIssueType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let job_id = self.id.parse::<i64>()?;
+        let mut stmt = reconciliation_issue::Entity::find()
+            .filter(reconciliation_issue::Column::JobId.eq(job_id));
+        if let Some(kind) = kind {
+            stmt = stmt.filter(reconciliation_issue::Column::Kind.eq(kind.as_str()));
+        }
+        if unresolved_only.unwrap_or(false) {
+            stmt = stmt.filter(reconciliation_issue::Column::ResolvedAt.is_null());
+        }
+        let issues = stmt
+            .order_by_asc(reconciliation_issue::Column::Id)
+            .all(db)
+            .await?;
+        Ok(issues.into_iter().map(|item| item.into()).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ReconciliationIssueType {
+    pub id: ID,
+    pub uuid: String,
+    pub kind: ReconciliationIssueKind,
+
+    #[graphql(skip)]
+    pub asset_id: Option<i64>,
+
+    pub collection_id: i64,
+    pub token_id: Option<String>,
+    pub detail: Option<String>,
+    /// The repair action applied to the issue
+    pub resolution: Option<String>,
+    pub resolved_at: Option<String>,
+    pub date_added: String,
+}
+
+impl From<reconciliation_issue::Model> for ReconciliationIssueType {
+    fn from(value: reconciliation_issue::Model) -> Self {
+        Self {
+            id: value.id.into(),
+            uuid: value.uuid.to_string(),
+            kind: value.kind.as_str().into(),
+            asset_id: value.asset_id,
+            collection_id: value.collection_id,
+            token_id: value.token_id,
+            detail: value.detail,
+            resolution: value.resolution,
+            resolved_at: value.resolved_at.map(|date| date.to_string()),
+            date_added: value.date_added.to_string(),
+        }
+    }
+}
+
+#[ComplexObject]
+impl ReconciliationIssueType {
+    async fn asset<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<AssetType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        if let Some(asset_id) = self.asset_id {
+            let asset = asset::Entity::find_by_id(asset_id as i32).one(db).await?;
+            Ok(asset.map(|item| item.into()))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -1,2 +1,3 @@
 pub mod graphql;
+pub mod routes;
 pub mod utils;
@@ -0,0 +1,343 @@
+use std::str::FromStr;
+
+use actix_files::NamedFile;
+use actix_web::{
+    error::{ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized},
+    get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpRequest, HttpResponse,
+};
+use entity::entities::{asset, client, export_job, folder, user};
+use sea_orm::{
+    entity::*, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::apps::{
+    assets::utils::{
+        certificates::{build_asset_certificate, folder_verification_url, verification_url},
+        encryption::fetch_asset_content,
+        exports::ExportJobStatus,
+        formating::format_id,
+        pinata::Pinata,
+        qrcodes::{qr_code_png, qr_code_size, qr_code_svg},
+    },
+    users::utils::auth::get_user_from_header,
+};
+use crate::config::state::AppState;
+
+#[derive(Serialize)]
+struct AssetVerification {
+    uuid: String,
+    name: String,
+    issuer: String,
+    code: String,
+    content_type: String,
+    ipfs_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plaintext_sha256: Option<String>,
+    nft_id: i64,
+    collection_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    minted_at: String,
+    verification_url: String,
+}
+
+#[derive(Serialize)]
+struct FolderVerification {
+    uuid: String,
+    name: String,
+    description: String,
+    collection_id: i32,
+    logo_url: String,
+    verification_url: String,
+}
+
+#[derive(Deserialize)]
+pub struct CertificateOptions {
+    pub qr_code: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct QrCodeOptions {
+    pub size: Option<u32>,
+}
+
+fn qr_code_response(
+    data: &str,
+    format: &str,
+    size: Option<u32>,
+) -> actix_web::Result<HttpResponse> {
+    let size = qr_code_size(size);
+    match format {
+        "png" => {
+            let png =
+                qr_code_png(data, size).map_err(|err| ErrorInternalServerError(err.message))?;
+            Ok(HttpResponse::Ok().content_type("image/png").body(png))
+        }
+        "svg" => {
+            let svg =
+                qr_code_svg(data, size).map_err(|err| ErrorInternalServerError(err.message))?;
+            Ok(HttpResponse::Ok().content_type("image/svg+xml").body(svg))
+        }
+        _ => Err(ErrorNotFound("Unsupported QR code format")),
+    }
+}
+
+/// The signed in user, failing with 401 without one and with 500 when the
+/// database could not be reached
+async fn authenticated_user(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    state: &AppState,
+) -> actix_web::Result<user::Model> {
+    let unauthorized = || ErrorUnauthorized("You must be authenticated to perform this action");
+    match get_user_from_header(req.headers(), db, state).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(unauthorized()),
+        Err(err) => match &err.source {
+            Some(source) if source.is::<DbErr>() => Err(ErrorInternalServerError(err.message)),
+            _ => Err(unauthorized()),
+        },
+    }
+}
+
+#[get("/exports/{uuid}")]
+pub async fn download_export(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<NamedFile> {
+    let user = authenticated_user(&req, &db, &state).await?;
+    let uuid = Uuid::from_str(path.as_str()).map_err(|_| ErrorNotFound("Export not found"))?;
+
+    let job = export_job::Entity::find()
+        .join(JoinType::InnerJoin, export_job::Relation::Client.def())
+        .filter(client::Column::UserId.eq(user.id))
+        .filter(export_job::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    match job {
+        Some(job) if ExportJobStatus::from(job.status.as_str()) == ExportJobStatus::Completed => {
+            if let Some(file_path) = job.file_path {
+                let file = NamedFile::open(file_path)?;
+                Ok(file.set_content_disposition(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(format!(
+                        "veecerts-export-{}.zip",
+                        job.uuid
+                    ))],
+                }))
+            } else {
+                Err(ErrorNotFound("Export not found"))
+            }
+        }
+        _ => Err(ErrorNotFound("Export not found")),
+    }
+}
+
+#[get("/assets/{uuid}/certificate.pdf")]
+pub async fn download_certificate(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    options: web::Query<CertificateOptions>,
+) -> actix_web::Result<HttpResponse> {
+    let user = authenticated_user(&req, &db, &state).await?;
+    let uuid = Uuid::from_str(path.as_str()).map_err(|_| ErrorNotFound("Asset not found"))?;
+
+    let asset = asset::Entity::find()
+        .join(JoinType::InnerJoin, asset::Relation::Client2.def())
+        .filter(client::Column::UserId.eq(user.id))
+        .filter(asset::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(asset) = asset {
+        let pdf = build_asset_certificate(&db, &state, &asset, options.qr_code)
+            .await
+            .map_err(|err| ErrorInternalServerError(err.message))?;
+        Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "certificate-{}.pdf",
+                    asset.uuid
+                ))],
+            })
+            .body(pdf))
+    } else {
+        Err(ErrorNotFound("Asset not found"))
+    }
+}
+
+/// Serves an asset's original content to its owner, decrypting it when the
+/// asset belongs to an encrypted folder
+#[get("/assets/{uuid}/content")]
+pub async fn download_asset(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    let user = authenticated_user(&req, &db, &state).await?;
+    let uuid = Uuid::from_str(path.as_str()).map_err(|_| ErrorNotFound("Asset not found"))?;
+
+    let asset = asset::Entity::find()
+        .join(JoinType::InnerJoin, asset::Relation::Client2.def())
+        .filter(client::Column::UserId.eq(user.id))
+        .filter(asset::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(asset) = asset {
+        let content = fetch_asset_content(&db, &state, &asset)
+            .await
+            .map_err(|err| ErrorInternalServerError(err.message))?;
+        let extension = mime_guess::get_mime_extensions_str(&asset.content_type)
+            .and_then(|extensions| extensions.first())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default();
+        Ok(HttpResponse::Ok()
+            .content_type(asset.content_type.as_str())
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Inline,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "{}{}",
+                    asset.name, extension
+                ))],
+            })
+            .body(content))
+    } else {
+        Err(ErrorNotFound("Asset not found"))
+    }
+}
+
+/// Public details a third party needs to check a certificate against the chain
+#[get("/verify/{uuid}")]
+pub async fn verify_asset(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    let uuid = Uuid::from_str(path.as_str()).map_err(|_| ErrorNotFound("Asset not found"))?;
+    let asset = asset::Entity::find()
+        .filter(asset::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(asset) = asset {
+        let folder = folder::Entity::find_by_id(asset.folder_id as i32)
+            .one(db.get_ref())
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+        Ok(HttpResponse::Ok().json(AssetVerification {
+            uuid: asset.uuid.to_string(),
+            name: asset.name,
+            issuer: folder.map(|item| item.name).unwrap_or_default(),
+            code: format_id(asset.id as u64),
+            content_type: asset.content_type,
+            ipfs_hash: asset.ipfs_hash,
+            plaintext_sha256: asset.plaintext_sha256,
+            nft_id: asset.nft_id,
+            collection_id: asset.folder_id,
+            owner: asset.owner_principal,
+            minted_at: asset.minted_at.to_string(),
+            verification_url: verification_url(&state, &asset.uuid),
+        }))
+    } else {
+        Err(ErrorNotFound("Asset not found"))
+    }
+}
+
+#[get("/verify/folders/{uuid}")]
+pub async fn verify_folder(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    let uuid = Uuid::from_str(path.as_str()).map_err(|_| ErrorNotFound("Folder not found"))?;
+    let folder = folder::Entity::find()
+        .filter(folder::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(folder) = folder {
+        Ok(HttpResponse::Ok().json(FolderVerification {
+            uuid: folder.uuid.to_string(),
+            name: folder.name,
+            description: folder.description,
+            collection_id: folder.id,
+            logo_url: Pinata::build_url(&state, folder.logo_hash),
+            verification_url: folder_verification_url(&state, &folder.uuid),
+        }))
+    } else {
+        Err(ErrorNotFound("Folder not found"))
+    }
+}
+
+/// QR code of the asset's verification URL, as `png` or `svg`
+#[get("/assets/{uuid}/qr.{format}")]
+pub async fn asset_qr_code(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    options: web::Query<QrCodeOptions>,
+) -> actix_web::Result<HttpResponse> {
+    let (uuid, format) = path.into_inner();
+    let uuid = Uuid::from_str(uuid.as_str()).map_err(|_| ErrorNotFound("Asset not found"))?;
+    let asset = asset::Entity::find()
+        .filter(asset::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(asset) = asset {
+        qr_code_response(
+            &verification_url(&state, &asset.uuid),
+            &format,
+            options.size,
+        )
+    } else {
+        Err(ErrorNotFound("Asset not found"))
+    }
+}
+
+/// QR code of the folder's verification URL, as `png` or `svg`
+#[get("/folders/{uuid}/qr.{format}")]
+pub async fn folder_qr_code(
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    options: web::Query<QrCodeOptions>,
+) -> actix_web::Result<HttpResponse> {
+    let (uuid, format) = path.into_inner();
+    let uuid = Uuid::from_str(uuid.as_str()).map_err(|_| ErrorNotFound("Folder not found"))?;
+    let folder = folder::Entity::find()
+        .filter(folder::Column::Uuid.eq(uuid))
+        .one(db.get_ref())
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(folder) = folder {
+        qr_code_response(
+            &folder_verification_url(&state, &folder.uuid),
+            &format,
+            options.size,
+        )
+    } else {
+        Err(ErrorNotFound("Folder not found"))
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use async_graphql::*;
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use super::{files::bytes_to_mb, uploads::PendingAsset};
+
+const MANIFEST_CSV: &str = "manifest.csv";
+const MANIFEST_JSON: &str = "manifest.json";
+
+/// A manifest row mapping a file in the archive to the asset's name and description
+#[derive(Deserialize)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+pub struct ArchiveEntry {
+    index: usize,
+    pub path: String,
+    pub directory: Option<String>,
+    pub size_mb: f64,
+    size: u64,
+}
+
+pub struct SkippedArchiveEntry {
+    pub path: String,
+    pub reason: String,
+}
+
+/// A ZIP upload opened for import. Opening only reads the central directory
+/// and the manifest, so the declared sizes can be checked against the quota
+/// before anything is extracted.
+pub struct AssetArchive {
+    archive: ZipArchive<std::fs::File>,
+    manifest: HashMap<String, ManifestEntry>,
+    pub entries: Vec<ArchiveEntry>,
+    pub skipped: Vec<SkippedArchiveEntry>,
+}
+
+impl AssetArchive {
+    pub fn open(file: std::fs::File, max_entries: usize) -> Result<AssetArchive> {
+        let mut archive = ZipArchive::new(file)?;
+        let manifest = read_manifest(&mut archive)?;
+
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            if entry.is_dir() {
+                continue;
+            }
+
+            let path = match entry.enclosed_name() {
+                Some(path) => path,
+                None => {
+                    skipped.push(SkippedArchiveEntry {
+                        path: entry.name().to_string(),
+                        reason: String::from("Unsafe file path"),
+                    });
+                    continue;
+                }
+            };
+            let path_str = path.to_string_lossy().replace('\\', "/");
+
+            if path_str == MANIFEST_CSV || path_str == MANIFEST_JSON {
+                continue;
+            }
+            if is_hidden(&path) {
+                skipped.push(SkippedArchiveEntry {
+                    path: path_str,
+                    reason: String::from("Hidden or system file"),
+                });
+                continue;
+            }
+            if entry.size() == 0 {
+                skipped.push(SkippedArchiveEntry {
+                    path: path_str,
+                    reason: String::from("Empty file"),
+                });
+                continue;
+            }
+            if entries.len() >= max_entries {
+                skipped.push(SkippedArchiveEntry {
+                    path: path_str,
+                    reason: format!("Archive contains more than {} files", max_entries),
+                });
+                continue;
+            }
+
+            let directory = path
+                .parent()
+                .map(|parent| parent.to_string_lossy().replace('\\', "/"))
+                .filter(|parent| !parent.is_empty());
+
+            entries.push(ArchiveEntry {
+                index,
+                path: path_str,
+                directory,
+                size_mb: bytes_to_mb(entry.size()),
+                size: entry.size(),
+            });
+        }
+
+        for file in manifest.keys() {
+            let listed = entries.iter().any(|entry| &entry.path == file)
+                || skipped.iter().any(|entry| &entry.path == file);
+            if !listed {
+                skipped.push(SkippedArchiveEntry {
+                    path: file.clone(),
+                    reason: String::from("Listed in the manifest but missing from the archive"),
+                });
+            }
+        }
+
+        Ok(AssetArchive {
+            archive,
+            manifest,
+            entries,
+            skipped,
+        })
+    }
+
+    pub fn total_size_mb(&self) -> f64 {
+        self.entries.iter().map(|entry| entry.size_mb).sum()
+    }
+
+    /// Extracts an entry to a temporary file, naming it from the manifest
+    /// when it has a row for the file and from the file name otherwise.
+    /// Entries inflating past their declared size are refused, since only
+    /// that size was checked against the quota.
+    pub fn extract(&mut self, entry: &ArchiveEntry, strip_gps: bool) -> Result<PendingAsset> {
+        let zip_file = self.archive.by_index(entry.index)?;
+        let mut content = tempfile::tempfile()?;
+        let size = io::copy(&mut zip_file.take(entry.size + 1), &mut content)?;
+        if size > entry.size {
+            return Err(Error::new(
+                "The file is larger than the size declared in the archive",
+            ));
+        }
+        content.seek(SeekFrom::Start(0))?;
+
+        let manifest_entry = self.manifest.get(&entry.path);
+        let name = manifest_entry
+            .and_then(|item| item.name.clone())
+            .unwrap_or_else(|| {
+                Path::new(&entry.path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| entry.path.clone())
+            });
+        let description = manifest_entry
+            .and_then(|item| item.description.clone())
+            .unwrap_or_default();
+        let content_type = mime_guess::from_path(&entry.path)
+            .first_or_octet_stream()
+            .to_string();
+
+        Ok(PendingAsset {
+            name,
+            description,
+            content_type: Some(content_type),
+            size_mb: bytes_to_mb(size),
+            content,
+            strip_gps,
+            owner: None,
+        })
+    }
+}
+
+fn read_manifest(
+    archive: &mut ZipArchive<std::fs::File>,
+) -> Result<HashMap<String, ManifestEntry>> {
+    let has_file = |archive: &ZipArchive<std::fs::File>, name: &str| {
+        archive.file_names().any(|file_name| file_name == name)
+    };
+
+    let entries: Vec<ManifestEntry> = if has_file(archive, MANIFEST_CSV) {
+        let mut reader = csv::Reader::from_reader(archive.by_name(MANIFEST_CSV)?);
+        let mut entries = Vec::new();
+        for row in reader.deserialize() {
+            let row: ManifestEntry =
+                row.map_err(|err| Error::new(format!("Invalid {}: {}", MANIFEST_CSV, err)))?;
+            entries.push(row);
+        }
+        entries
+    } else if has_file(archive, MANIFEST_JSON) {
+        serde_json::from_reader(archive.by_name(MANIFEST_JSON)?)
+            .map_err(|err| Error::new(format!("Invalid {}: {}", MANIFEST_JSON, err)))?
+    } else {
+        Vec::new()
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.file.trim_start_matches("./").to_string(), entry))
+        .collect())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| {
+        let component = component.as_os_str().to_string_lossy();
+        component.starts_with('.') || component == "__MACOSX"
+    })
+}
@@ -0,0 +1,254 @@
+use actix_web::web;
+use async_graphql::*;
+use entity::entities::{asset, folder};
+use image::DynamicImage;
+use printpdf::{BuiltinFont, Image, ImageTransform, Mm, PdfDocument};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{formating::format_id, pinata::Pinata, qrcodes::qr_code_image};
+use crate::config::state::AppState;
+
+const MM_PER_INCH: f32 = 25.4;
+const QR_CODE_DPI: f32 = 300.0;
+
+/// Layout of a certificate. A JSON file with the same shape can be pointed
+/// to with `CERTIFICATE_TEMPLATE`; missing keys fall back to the defaults.
+/// Text may contain the placeholders `{asset_name}`, `{asset_description}`,
+/// `{folder_name}`, `{code}`, `{ipfs_hash}`, `{nft_id}`, `{minted_at}` and
+/// `{verification_url}`. With `qr_code` set, a QR code of the verification
+/// URL is printed in the bottom right corner.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct CertificateTemplate {
+    pub page_width_mm: f32,
+    pub page_height_mm: f32,
+    pub margin_mm: f32,
+    pub logo_size_mm: f32,
+    pub qr_code: bool,
+    pub qr_code_size_mm: f32,
+    pub title: String,
+    pub subtitle: String,
+    pub fields: Vec<CertificateField>,
+    pub footer: String,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CertificateField {
+    pub label: String,
+    pub value: String,
+}
+
+impl Default for CertificateTemplate {
+    fn default() -> Self {
+        let field = |label: &str, value: &str| CertificateField {
+            label: label.to_string(),
+            value: value.to_string(),
+        };
+        Self {
+            page_width_mm: 297.0,
+            page_height_mm: 210.0,
+            margin_mm: 20.0,
+            logo_size_mm: 30.0,
+            qr_code: false,
+            qr_code_size_mm: 35.0,
+            title: String::from("Certificate of Authenticity"),
+            subtitle: String::from("Issued by {folder_name}"),
+            fields: vec![
+                field("Asset", "{asset_name}"),
+                field("Certificate code", "{code}"),
+                field("IPFS hash", "{ipfs_hash}"),
+                field("NFT id", "{nft_id}"),
+                field("Minted at", "{minted_at}"),
+            ],
+            footer: String::from("Verify this certificate at {verification_url}"),
+        }
+    }
+}
+
+impl CertificateTemplate {
+    /// The template at `CERTIFICATE_TEMPLATE`, the default one without it.
+    /// Checked with the settings and read once into the `AppState`.
+    pub fn load(path: Option<&str>) -> Result<CertificateTemplate> {
+        if let Some(path) = path {
+            let content = std::fs::read_to_string(path)?;
+            serde_json::from_str(&content).map_err(|err| {
+                Error::new(format!("Invalid certificate template {}: {}", path, err))
+            })
+        } else {
+            Ok(CertificateTemplate::default())
+        }
+    }
+}
+
+pub struct CertificateData {
+    pub asset_name: String,
+    pub asset_description: String,
+    pub folder_name: String,
+    pub code: String,
+    pub ipfs_hash: String,
+    pub nft_id: i64,
+    pub minted_at: String,
+    pub verification_url: String,
+}
+
+impl CertificateData {
+    fn fill(&self, text: &str) -> String {
+        text.replace("{asset_name}", &self.asset_name)
+            .replace("{asset_description}", &self.asset_description)
+            .replace("{folder_name}", &self.folder_name)
+            .replace("{code}", &self.code)
+            .replace("{ipfs_hash}", &self.ipfs_hash)
+            .replace("{nft_id}", &self.nft_id.to_string())
+            .replace("{minted_at}", &self.minted_at)
+            .replace("{verification_url}", &self.verification_url)
+    }
+}
+
+/// Public page a third party can use to check an asset
+pub fn verification_url(state: &AppState, uuid: &Uuid) -> String {
+    format!("{}/verify/{}", state.env.public_url, uuid)
+}
+
+pub fn folder_verification_url(state: &AppState, uuid: &Uuid) -> String {
+    format!("{}/verify/folders/{}", state.env.public_url, uuid)
+}
+
+pub fn certificate_pdf_path(uuid: &str) -> String {
+    format!("/assets/{}/certificate.pdf", uuid)
+}
+
+pub fn render_certificate(
+    template: &CertificateTemplate,
+    data: &CertificateData,
+    logo: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(
+        data.fill(&template.title),
+        Mm(template.page_width_mm),
+        Mm(template.page_height_mm),
+        "Certificate",
+    );
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|err| Error::new(err.to_string()))?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|err| Error::new(err.to_string()))?;
+
+    let left = template.margin_mm;
+    let top = template.page_height_mm - template.margin_mm;
+
+    if let Some(logo) = logo.and_then(|bytes| image::load_from_memory(bytes).ok()) {
+        let largest_side = logo.width().max(logo.height()) as f32;
+        let dpi = largest_side / (template.logo_size_mm / MM_PER_INCH);
+        Image::from_dynamic_image(&logo).add_to_layer(
+            layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(template.page_width_mm
+                    - template.margin_mm
+                    - template.logo_size_mm)),
+                translate_y: Some(Mm(top - template.logo_size_mm)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    if template.qr_code {
+        let size_px = (template.qr_code_size_mm / MM_PER_INCH * QR_CODE_DPI) as u32;
+        let qr_code = qr_code_image(&data.verification_url, size_px)?;
+        let dpi = qr_code.width() as f32 / (template.qr_code_size_mm / MM_PER_INCH);
+        Image::from_dynamic_image(&DynamicImage::ImageLuma8(qr_code)).add_to_layer(
+            layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(template.page_width_mm
+                    - template.margin_mm
+                    - template.qr_code_size_mm)),
+                translate_y: Some(Mm(template.margin_mm)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    layer.use_text(
+        data.fill(&template.title),
+        26.0,
+        Mm(left),
+        Mm(top - 10.0),
+        &bold,
+    );
+    layer.use_text(
+        data.fill(&template.subtitle),
+        14.0,
+        Mm(left),
+        Mm(top - 20.0),
+        &regular,
+    );
+
+    let mut y = top - 45.0;
+    for field in &template.fields {
+        layer.use_text(data.fill(&field.label), 10.0, Mm(left), Mm(y), &bold);
+        layer.use_text(
+            data.fill(&field.value),
+            12.0,
+            Mm(left),
+            Mm(y - 6.0),
+            &regular,
+        );
+        y -= 16.0;
+    }
+
+    layer.use_text(
+        data.fill(&template.footer),
+        10.0,
+        Mm(left),
+        Mm(template.margin_mm),
+        &regular,
+    );
+
+    doc.save_to_bytes()
+        .map_err(|err| Error::new(err.to_string()))
+}
+
+/// Renders the certificate of an asset with its folder's logo. `qr_code`
+/// overrides whether the template embeds the verification QR code.
+pub async fn build_asset_certificate(
+    db: &DatabaseConnection,
+    state: &AppState,
+    asset: &asset::Model,
+    qr_code: Option<bool>,
+) -> Result<Vec<u8>> {
+    let folder = folder::Entity::find_by_id(asset.folder_id as i32)
+        .one(db)
+        .await?;
+    let folder = match folder {
+        Some(folder) => folder,
+        None => return Err(Error::new("Asset folder was not found")),
+    };
+
+    // A certificate without the logo is still useful, so storage errors are ignored
+    let logo = Pinata::fetch_file(state, &folder.logo_hash).await.ok();
+
+    let data = CertificateData {
+        asset_name: asset.name.clone(),
+        asset_description: asset.description.clone(),
+        folder_name: folder.name,
+        code: format_id(asset.id as u64),
+        ipfs_hash: asset.ipfs_hash.clone(),
+        nft_id: asset.nft_id,
+        minted_at: asset.minted_at.to_string(),
+        verification_url: verification_url(state, &asset.uuid),
+    };
+    let mut template = state.certificate_template.clone();
+    if let Some(qr_code) = qr_code {
+        template.qr_code = qr_code;
+    }
+    // Rendering is CPU bound, so it leaves the worker's event loop
+    web::block(move || render_certificate(&template, &data, logo.as_deref()))
+        .await
+        .map_err(|err| Error::new(err.to_string()))?
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_graphql::*;
+
+use super::contract::{Contract, NFTCollectionDetails, NFTDetails, TokenId};
+use crate::config::state::AppState;
+
+/// Expired entries are only swept once the cache grows past this
+const CACHE_SWEEP_SIZE: usize = 10_000;
+
+/// Tokens and collections as the canister returned them, kept for
+/// `CHAIN_CACHE_TTL_SECS`
+pub struct ChainCache {
+    nfts: TtlCache<TokenId, Option<NFTDetails>>,
+    collections: TtlCache<u64, Option<NFTCollectionDetails>>,
+}
+
+impl ChainCache {
+    pub fn new(ttl_secs: u64) -> ChainCache {
+        let ttl = Duration::from_secs(ttl_secs);
+        ChainCache {
+            nfts: TtlCache::new(ttl),
+            collections: TtlCache::new(ttl),
+        }
+    }
+}
+
+struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().ok()?;
+        match entries.get(key) {
+            Some((cached_at, value)) if cached_at.elapsed() < self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, key: K, value: V) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= CACHE_SWEEP_SIZE {
+                let ttl = self.ttl;
+                entries.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+            }
+            entries.insert(key, (Instant::now(), value));
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(key);
+        }
+    }
+}
+
+/// The token as the canister currently sees it, cached for
+/// `CHAIN_CACHE_TTL_SECS`. Missing tokens are cached too.
+pub async fn cached_nft(state: &AppState, token_id: TokenId) -> Result<Option<NFTDetails>> {
+    let cache = &state.chain_cache.nfts;
+    if let Some(nft) = cache.get(&token_id) {
+        return Ok(nft);
+    }
+    let nft = Contract::get_nft(state, token_id).await?;
+    cache.insert(token_id, nft.clone());
+    Ok(nft)
+}
+
+pub async fn cached_collection(
+    state: &AppState,
+    collection_id: u64,
+) -> Result<Option<NFTCollectionDetails>> {
+    let cache = &state.chain_cache.collections;
+    if let Some(collection) = cache.get(&collection_id) {
+        return Ok(collection);
+    }
+    let collection = Contract::get_collection(state, collection_id).await?;
+    cache.insert(collection_id, collection.clone());
+    Ok(collection)
+}
+
+/// Drops a token whose owner or existence we just changed
+pub fn invalidate_nft(state: &AppState, token_id: &TokenId) {
+    state.chain_cache.nfts.remove(token_id);
+}
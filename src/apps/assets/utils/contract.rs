@@ -1,19 +1,31 @@
+use std::{fmt, str::FromStr};
+
 use async_graphql::*;
-use candid::{Decode, Encode, Principal};
+use candid::{
+    encode_args,
+    types::{Serializer, Type},
+    CandidType, Decode, Principal,
+};
 use chrono::Utc;
+use entity::entities::asset;
 use ic_agent::Agent;
 use serde::Serialize;
 
-use crate::config::settings::ENV;
+use super::chain::invalidate_nft;
+use crate::config::{metrics::observe_call, state::AppState};
 
+/// The metadata minted with every asset NFT
 #[derive(Serialize, candid::Deserialize)]
-struct Asset {
-    uuid: String,
-    ipfs_hash: String,
-    date_added: String,
+pub struct Asset {
+    pub uuid: String,
+    pub ipfs_hash: String,
+    /// Digest of the content before encryption, `ipfs_hash` then points to the ciphertext
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plaintext_sha256: Option<String>,
+    pub date_added: String,
 }
 
-#[derive(candid::CandidType, candid::Deserialize, Debug)]
+#[derive(candid::CandidType, candid::Deserialize, Debug, Clone)]
 pub struct NFTDetails {
     pub id: u64,
     pub owner: Principal,
@@ -21,6 +33,71 @@ pub struct NFTDetails {
     pub collection_id: u64,
 }
 
+impl NFTDetails {
+    /// The asset the token was minted for, `None` when its metadata is not ours
+    pub fn asset(&self) -> Option<Asset> {
+        serde_json::from_str(&self.metadata).ok()
+    }
+
+    pub fn token_id(&self) -> TokenId {
+        TokenId::new(self.id, self.collection_id)
+    }
+}
+
+/// A token of one collection, `{nft_id}x{collection_id}` on the canister
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenId {
+    pub nft_id: u64,
+    pub collection_id: u64,
+}
+
+impl TokenId {
+    pub fn new(nft_id: u64, collection_id: u64) -> TokenId {
+        TokenId {
+            nft_id,
+            collection_id,
+        }
+    }
+
+    /// The token an asset was minted as, its folder being the collection
+    pub fn of(asset: &asset::Model) -> TokenId {
+        TokenId::new(asset.nft_id as u64, asset.folder_id as u64)
+    }
+}
+
+impl fmt::Display for TokenId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.nft_id, self.collection_id)
+    }
+}
+
+impl FromStr for TokenId {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let parsed = value.split_once('x').and_then(|(nft_id, collection_id)| {
+            Some(TokenId::new(
+                nft_id.parse().ok()?,
+                collection_id.parse().ok()?,
+            ))
+        });
+        match parsed {
+            Some(token_id) => Ok(token_id),
+            None => Err(Error::new(format!("Invalid token id {}", value))),
+        }
+    }
+}
+
+impl CandidType for TokenId {
+    fn _ty() -> Type {
+        String::ty()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<(), S::Error> {
+        serializer.serialize_text(&self.to_string())
+    }
+}
+
 #[derive(candid::CandidType, candid::Deserialize, Debug)]
 pub struct MintNFTSuccess(pub u128, pub NFTDetails);
 
@@ -56,6 +133,12 @@ pub enum BurnNFTResult {
 }
 
 #[derive(candid::CandidType, candid::Deserialize, Debug)]
+pub enum TransferNFTResult {
+    Ok(u128),
+    Err(NFTError),
+}
+
+#[derive(candid::CandidType, candid::Deserialize, Debug, Clone)]
 pub struct NFTCollectionDetails {
     pub id: u64,
     pub owner: Principal,
@@ -77,76 +160,308 @@ pub enum CreateNFTResult {
     Err(NFTError),
 }
 
+// Arguments of each canister method, which the tests check against
+// candid/nft.did
+type CreateNftArgs = (String, String, String, Option<String>);
+type MintNftArgs = (u64, String, Option<Principal>);
+type TokenArgs = (TokenId,);
+type TransferArgs = (TokenId, Principal);
+type CollectionArgs = (u64,);
+
+/// Parses principal text such as a wallet address
+pub fn parse_principal(text: &str) -> Result<Principal> {
+    Principal::from_text(text.trim())
+        .map_err(|err| Error::new(format!("Invalid principal {}: {}", text, err)))
+}
+
 pub struct Contract;
 
 impl Contract {
-    fn init() -> Result<(Principal, Agent)> {
-        let env = ENV::init();
-        let icp_agent_endpoint = env.icp_agent_endpoint;
-        let canister_principal_id = env.canister_principal_id;
-        let canister_id = Principal::from_text(canister_principal_id.as_str())?;
-        let agent = Agent::builder().with_url(icp_agent_endpoint).build()?;
-        Ok((canister_id, agent))
+    fn init(state: &AppState) -> Result<(&Principal, &Agent)> {
+        let icp = state.icp()?;
+        Ok((&icp.canister_id, &icp.agent))
     }
 
+    /// Principal the backend calls the canister as, which owns the NFTs it
+    /// mints without a recipient
+    pub fn principal(state: &AppState) -> Result<Principal> {
+        state.icp()?.agent.get_principal().map_err(Error::new)
+    }
+
+    /// Mints into the collection, owned by `owner` when given and by the
+    /// backend otherwise
     pub async fn mint_nft(
+        state: &AppState,
         collection_id: u64,
         uuid: &String,
         ipfs_hash: &str,
+        plaintext_sha256: Option<&str>,
+        owner: Option<Principal>,
     ) -> Result<MintNFTResult> {
-        let (canister_id, agent) = Contract::init()?;
+        let (canister_id, agent) = Contract::init(state)?;
         let method_name = "mint_nft";
         let contract_asset = Asset {
             uuid: uuid.to_string(),
             ipfs_hash: ipfs_hash.to_owned(),
+            plaintext_sha256: plaintext_sha256.map(|digest| digest.to_owned()),
             date_added: Utc::now().to_string(),
         };
         let metadata = serde_json::to_string(&contract_asset)?;
 
-        let args = Encode!(&collection_id, &metadata)?;
+        let args: MintNftArgs = (collection_id, metadata, owner);
+        let args = encode_args(args)?;
 
-        let response = agent
-            .update(&canister_id, method_name)
-            .with_arg(args)
-            .call_and_wait()
-            .await?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent
+                .update(canister_id, method_name)
+                .with_arg(args)
+                .call_and_wait(),
+        )
+        .await?;
 
         let result = Decode!(&response, MintNFTResult)?;
+        if let MintNFTResult::Ok(res) = &result {
+            invalidate_nft(state, &res.1.token_id());
+        }
         Ok(result)
     }
 
-    pub async fn burn_nft(token_id: String) -> Result<BurnNFTResult> {
-        let (canister_id, agent) = Contract::init()?;
+    pub async fn burn_nft(state: &AppState, token_id: TokenId) -> Result<BurnNFTResult> {
+        let (canister_id, agent) = Contract::init(state)?;
         let method_name = "burn_nft";
 
-        let args = Encode!(&token_id)?;
-        let response = agent
-            .update(&canister_id, method_name)
-            .with_arg(args)
-            .call_and_wait()
-            .await?;
+        let args: TokenArgs = (token_id,);
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent
+                .update(canister_id, method_name)
+                .with_arg(args)
+                .call_and_wait(),
+        )
+        .await?;
+        invalidate_nft(state, &token_id);
 
         let result = Decode!(&response, BurnNFTResult)?;
         Ok(result)
     }
 
+    /// Moves the token out of the backend's identity to `to`
+    pub async fn transfer_nft(
+        state: &AppState,
+        token_id: TokenId,
+        to: Principal,
+    ) -> Result<TransferNFTResult> {
+        let (canister_id, agent) = Contract::init(state)?;
+        let method_name = "transfer";
+
+        let args: TransferArgs = (token_id, to);
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent
+                .update(canister_id, method_name)
+                .with_arg(args)
+                .call_and_wait(),
+        )
+        .await?;
+        invalidate_nft(state, &token_id);
+
+        let result = Decode!(&response, TransferNFTResult)?;
+        Ok(result)
+    }
+
+    pub async fn get_nft(state: &AppState, token_id: TokenId) -> Result<Option<NFTDetails>> {
+        let (canister_id, agent) = Contract::init(state)?;
+        let method_name = "get_nft";
+
+        let args: TokenArgs = (token_id,);
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent.query(canister_id, method_name).with_arg(args).call(),
+        )
+        .await?;
+
+        let result = Decode!(&response, Option<NFTDetails>)?;
+        Ok(result)
+    }
+
+    pub async fn get_collection(
+        state: &AppState,
+        collection_id: u64,
+    ) -> Result<Option<NFTCollectionDetails>> {
+        let (canister_id, agent) = Contract::init(state)?;
+        let method_name = "get_collection";
+
+        let args: CollectionArgs = (collection_id,);
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent.query(canister_id, method_name).with_arg(args).call(),
+        )
+        .await?;
+
+        let result = Decode!(&response, Option<NFTCollectionDetails>)?;
+        Ok(result)
+    }
+
+    /// Every live token of the collection
+    pub async fn get_collection_nfts(
+        state: &AppState,
+        collection_id: u64,
+    ) -> Result<Vec<NFTDetails>> {
+        let (canister_id, agent) = Contract::init(state)?;
+        let method_name = "get_collection_nfts";
+
+        let args: CollectionArgs = (collection_id,);
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent.query(canister_id, method_name).with_arg(args).call(),
+        )
+        .await?;
+
+        let result = Decode!(&response, Vec<NFTDetails>)?;
+        Ok(result)
+    }
+
     pub async fn create_nft(
-        name: &String,
-        symbol: &String,
-        description: &String,
+        state: &AppState,
+        name: &str,
+        symbol: &str,
+        description: &str,
         logo: &Option<String>,
     ) -> Result<CreateNFTResult> {
-        let (canister_id, agent) = Contract::init()?;
+        let (canister_id, agent) = Contract::init(state)?;
         let method_name = "create_nft";
 
-        let args = Encode!(name, symbol, description, logo)?;
-        let response = agent
-            .update(&canister_id, method_name)
-            .with_arg(args)
-            .call_and_wait()
-            .await?;
+        let args: CreateNftArgs = (
+            name.to_owned(),
+            symbol.to_owned(),
+            description.to_owned(),
+            logo.clone(),
+        );
+        let args = encode_args(args)?;
+        let response = observe_call(
+            "icp",
+            method_name,
+            agent
+                .update(canister_id, method_name)
+                .with_arg(args)
+                .call_and_wait(),
+        )
+        .await?;
 
         let result = Decode!(&response, CreateNFTResult)?;
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use candid::types::{internal::TypeContainer, FuncMode, Function, TypeInner};
+    use candid_parser::utils::{service_equal, CandidSource};
+
+    use super::*;
+
+    /// Types of an argument tuple, in order
+    trait ArgumentTypes {
+        fn types(env: &mut TypeContainer) -> Vec<Type>;
+    }
+
+    macro_rules! argument_types {
+        ($($arg:ident),*) => {
+            impl<$($arg: CandidType),*> ArgumentTypes for ($($arg,)*) {
+                fn types(env: &mut TypeContainer) -> Vec<Type> {
+                    vec![$(env.add::<$arg>()),*]
+                }
+            }
+        };
+    }
+
+    argument_types!(A);
+    argument_types!(A, B);
+    argument_types!(A, B, C);
+    argument_types!(A, B, C, D);
+
+    /// A method as `Contract` encodes its arguments and decodes its results
+    macro_rules! method {
+        ($env:ident, $args:ident -> ($($ret:ty),*) $(, $mode:expr)?) => {
+            Type::from(TypeInner::Func(Function {
+                args: <$args as ArgumentTypes>::types(&mut $env),
+                rets: vec![$($env.add::<$ret>()),*],
+                modes: vec![$($mode)?],
+            }))
+        };
+    }
+
+    fn contract_interface() -> String {
+        let mut env = TypeContainer::new();
+        let mut service = vec![
+            (
+                "create_nft".to_string(),
+                method!(env, CreateNftArgs -> (CreateNFTResult)),
+            ),
+            (
+                "mint_nft".to_string(),
+                method!(env, MintNftArgs -> (MintNFTResult)),
+            ),
+            (
+                "burn_nft".to_string(),
+                method!(env, TokenArgs -> (BurnNFTResult)),
+            ),
+            (
+                "transfer".to_string(),
+                method!(env, TransferArgs -> (TransferNFTResult)),
+            ),
+            (
+                "get_nft".to_string(),
+                method!(env, TokenArgs -> (Option<NFTDetails>), FuncMode::Query),
+            ),
+            (
+                "get_collection".to_string(),
+                method!(env, CollectionArgs -> (Option<NFTCollectionDetails>), FuncMode::Query),
+            ),
+            (
+                "get_collection_nfts".to_string(),
+                method!(env, CollectionArgs -> (Vec<NFTDetails>), FuncMode::Query),
+            ),
+        ];
+        service.sort_by(|left, right| left.0.cmp(&right.0));
+        let service = TypeInner::Service(service).into();
+        candid::pretty::candid::compile(&env.env, &Some(service))
+    }
+
+    #[test]
+    fn contract_matches_canister_interface() {
+        let interface = contract_interface();
+        if let Err(err) = service_equal(
+            CandidSource::File(Path::new("candid/nft.did")),
+            CandidSource::Text(&interface),
+        ) {
+            panic!(
+                "Contract bindings drifted from candid/nft.did: {}\n\nBindings:\n{}",
+                err, interface
+            );
+        }
+    }
+
+    #[test]
+    fn token_id_round_trips() {
+        let token_id = TokenId::new(12, 3);
+        assert_eq!(token_id.to_string(), "12x3");
+        assert_eq!(TokenId::from_str("12x3").unwrap(), token_id);
+        assert!(TokenId::from_str("12").is_err());
+        assert!(TokenId::from_str("x3").is_err());
+    }
+}
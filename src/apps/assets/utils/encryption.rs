@@ -0,0 +1,216 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use async_graphql::*;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use entity::entities::{asset, client};
+use hkdf::Hkdf;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::pinata::Pinata;
+use crate::config::{settings::ENV, state::AppState};
+
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Wraps the per-asset data keys with a per-client key that never leaves the
+/// provider. `key_id` is stored with every wrapped key so assets keep
+/// decrypting after the provider's key is rotated.
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    fn key_id(&self) -> &str;
+    async fn wrap_key(&self, client_uuid: &Uuid, data_key: &[u8]) -> Result<Vec<u8>>;
+    async fn unwrap_key(
+        &self,
+        key_id: &str,
+        client_uuid: &Uuid,
+        wrapped_key: &[u8],
+    ) -> Result<Vec<u8>>;
+}
+
+/// Derives each client's key from a master key kept in a local file. Meant
+/// for development, the file must hold 32 bytes encoded as hex or base64.
+pub struct LocalKeyProvider {
+    master_key: Vec<u8>,
+    key_id: String,
+}
+
+impl LocalKeyProvider {
+    pub fn from_file(path: &str) -> Result<LocalKeyProvider> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| Error::new(format!("Unable to read key file {}: {}", path, err)))?;
+        let content = content.trim();
+        let master_key = hex::decode(content)
+            .ok()
+            .or_else(|| STANDARD.decode(content).ok())
+            .filter(|key| key.len() == KEY_LEN);
+
+        if let Some(master_key) = master_key {
+            let fingerprint = hex::encode(Sha256::digest(&master_key));
+            Ok(LocalKeyProvider {
+                key_id: format!("local:{}", &fingerprint[..16]),
+                master_key,
+            })
+        } else {
+            Err(Error::new(format!(
+                "Key file {} must contain a {} byte key encoded as hex or base64",
+                path, KEY_LEN
+            )))
+        }
+    }
+
+    fn client_cipher(&self, client_uuid: &Uuid) -> Result<Aes256Gcm> {
+        let mut key = [0u8; KEY_LEN];
+        Hkdf::<Sha256>::new(None, &self.master_key)
+            .expand(client_uuid.as_bytes(), &mut key)
+            .map_err(|err| Error::new(err.to_string()))?;
+        Aes256Gcm::new_from_slice(&key).map_err(|err| Error::new(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn wrap_key(&self, client_uuid: &Uuid, data_key: &[u8]) -> Result<Vec<u8>> {
+        seal(
+            &self.client_cipher(client_uuid)?,
+            data_key,
+            client_uuid.as_bytes(),
+        )
+    }
+
+    async fn unwrap_key(
+        &self,
+        key_id: &str,
+        client_uuid: &Uuid,
+        wrapped_key: &[u8],
+    ) -> Result<Vec<u8>> {
+        if key_id != self.key_id {
+            return Err(Error::new(format!(
+                "Data key was wrapped with {} which is not the configured key",
+                key_id
+            )));
+        }
+        open(
+            &self.client_cipher(client_uuid)?,
+            wrapped_key,
+            client_uuid.as_bytes(),
+        )
+    }
+}
+
+/// The provider selected by `KEY_PROVIDER`, `None` when it has no key to
+/// encrypt with
+pub fn load_key_provider(env: &ENV) -> Result<Option<Box<dyn KeyProvider>>> {
+    match env.key_provider.as_str() {
+        "local" => match &env.key_file {
+            Some(key_file) => Ok(Some(Box::new(LocalKeyProvider::from_file(key_file)?))),
+            None => Ok(None),
+        },
+        provider => Err(Error::new(format!("Unknown key provider {}", provider))),
+    }
+}
+
+/// Encrypts with a random nonce and returns `nonce || ciphertext`
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::new("Failed to encrypt content"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::new("Encrypted content is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::new("Failed to decrypt content"))
+}
+
+pub struct EncryptedContent {
+    pub ciphertext: Vec<u8>,
+    /// Base64 of the data key wrapped by the key provider
+    pub encrypted_data_key: String,
+    pub key_id: String,
+    pub plaintext_sha256: String,
+}
+
+/// Encrypts an asset's content with a fresh data key. The asset's uuid is
+/// authenticated with the content so ciphertexts cannot be swapped between
+/// assets.
+pub async fn encrypt_content(
+    state: &AppState,
+    client_uuid: &Uuid,
+    asset_uuid: &Uuid,
+    plaintext: &[u8],
+) -> Result<EncryptedContent> {
+    let provider = state.key_provider()?;
+    let data_key = Aes256Gcm::generate_key(OsRng);
+    let ciphertext = seal(&Aes256Gcm::new(&data_key), plaintext, asset_uuid.as_bytes())?;
+    let wrapped_key = provider.wrap_key(client_uuid, &data_key).await?;
+
+    Ok(EncryptedContent {
+        ciphertext,
+        encrypted_data_key: STANDARD.encode(wrapped_key),
+        key_id: provider.key_id().to_string(),
+        plaintext_sha256: hex::encode(Sha256::digest(plaintext)),
+    })
+}
+
+/// Fetches an asset's content from storage, decrypting it for assets of
+/// encrypted folders
+pub async fn fetch_asset_content(
+    db: &DatabaseConnection,
+    state: &AppState,
+    asset: &asset::Model,
+) -> Result<Vec<u8>> {
+    let content = Pinata::fetch_file(state, &asset.ipfs_hash).await?;
+
+    if let (Some(encrypted_data_key), Some(key_id)) =
+        (&asset.encrypted_data_key, &asset.encryption_key_id)
+    {
+        let client = client::Entity::find_by_id(asset.client_id as i32)
+            .one(db)
+            .await?;
+        let client = match client {
+            Some(client) => client,
+            None => return Err(Error::new("Asset client was not found")),
+        };
+
+        let wrapped_key = STANDARD.decode(encrypted_data_key)?;
+        let data_key = state
+            .key_provider()?
+            .unwrap_key(key_id, &client.uuid, &wrapped_key)
+            .await?;
+        let cipher =
+            Aes256Gcm::new_from_slice(&data_key).map_err(|err| Error::new(err.to_string()))?;
+        open(&cipher, &content, asset.uuid.as_bytes())
+    } else {
+        Ok(content)
+    }
+}
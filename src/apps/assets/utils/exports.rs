@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_graphql::*;
+use chrono::{Duration, Utc};
+use entity::entities::{asset, client, export_job, folder};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::{encryption::fetch_asset_content, pinata::Pinata};
+use crate::config::state::AppState;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ExportJobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    /// The archive was deleted after `EXPORT_RETENTION_HOURS`
+    Expired,
+}
+
+impl ExportJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportJobStatus::Pending => "pending",
+            ExportJobStatus::Running => "running",
+            ExportJobStatus::Completed => "completed",
+            ExportJobStatus::Failed => "failed",
+            ExportJobStatus::Expired => "expired",
+        }
+    }
+}
+
+impl From<&str> for ExportJobStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "running" => ExportJobStatus::Running,
+            "completed" => ExportJobStatus::Completed,
+            "failed" => ExportJobStatus::Failed,
+            "expired" => ExportJobStatus::Expired,
+            _ => ExportJobStatus::Pending,
+        }
+    }
+}
+
+/// One row of the export manifest, written both to `manifest.json` and `manifest.csv`
+#[derive(Serialize)]
+struct ManifestAsset {
+    uuid: String,
+    name: String,
+    description: String,
+    folder_uuid: String,
+    folder_name: String,
+    file: String,
+    content_type: String,
+    size_mb: f64,
+    sha256: String,
+    ipfs_hash: String,
+    nft_id: i64,
+    collection_id: i64,
+    date_added: String,
+    last_updated: String,
+}
+
+#[derive(Serialize)]
+struct ExportManifest {
+    export_uuid: String,
+    client_uuid: String,
+    folder_uuid: Option<String>,
+    generated_at: String,
+    asset_count: usize,
+    assets: Vec<ManifestAsset>,
+}
+
+pub fn export_file_path(state: &AppState, uuid: &Uuid) -> PathBuf {
+    Path::new(&state.env.exports_dir).join(format!("{}.zip", uuid))
+}
+
+/// Builds the archive for an export job, recording the failure on the job
+/// instead of returning it since this runs detached from any request
+pub async fn run_export_job(db: DatabaseConnection, state: Arc<AppState>, job_id: i32) {
+    if let Err(err) = build_export(&db, &state, job_id).await {
+        if let Ok(Some(job)) = export_job::Entity::find_by_id(job_id).one(&db).await {
+            let mut job: export_job::ActiveModel = job.into();
+            job.status = Set(ExportJobStatus::Failed.as_str().to_string());
+            job.error = Set(Some(err.message));
+            job.completed_at = Set(Some(Utc::now().naive_utc()));
+            let _ = job.update(&db).await;
+        }
+    }
+}
+
+async fn build_export(db: &DatabaseConnection, state: &AppState, job_id: i32) -> Result<()> {
+    let job = match export_job::Entity::find_by_id(job_id).one(db).await? {
+        Some(job) => job,
+        None => return Err(Error::new(format!("Export job {} was not found", job_id))),
+    };
+    let mut running: export_job::ActiveModel = job.clone().into();
+    running.status = Set(ExportJobStatus::Running.as_str().to_string());
+    running.update(db).await?;
+
+    let client = match client::Entity::find_by_id(job.client_id as i32)
+        .one(db)
+        .await?
+    {
+        Some(client) => client,
+        None => return Err(Error::new("Export client was not found")),
+    };
+    let folders: HashMap<i64, folder::Model> = folder::Entity::find()
+        .filter(folder::Column::ClientId.eq(job.client_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|item| (item.id as i64, item))
+        .collect();
+
+    let mut stmt = asset::Entity::find().filter(asset::Column::ClientId.eq(job.client_id));
+    if let Some(folder_id) = job.folder_id {
+        stmt = stmt.filter(asset::Column::FolderId.eq(folder_id));
+    }
+    let assets = stmt
+        .order_by_asc(asset::Column::FolderId)
+        .order_by_asc(asset::Column::DateAdded)
+        .all(db)
+        .await?;
+
+    fs::create_dir_all(&state.env.exports_dir)?;
+    let path = export_file_path(state, &job.uuid);
+    // Written aside and renamed once complete, so no download sees a partial archive
+    let partial_path = path.with_extension("zip.part");
+    let written = write_archive(db, state, &job, &client, &folders, assets, &partial_path)
+        .await
+        .and_then(|asset_count| {
+            fs::rename(&partial_path, &path)?;
+            Ok(asset_count)
+        });
+    let asset_count = match written {
+        Ok(asset_count) => asset_count,
+        Err(err) => {
+            let _ = fs::remove_file(&partial_path);
+            return Err(err);
+        }
+    };
+
+    let mut completed: export_job::ActiveModel = job.into();
+    completed.status = Set(ExportJobStatus::Completed.as_str().to_string());
+    completed.asset_count = Set(asset_count as i32);
+    completed.file_path = Set(Some(path.to_string_lossy().to_string()));
+    completed.completed_at = Set(Some(Utc::now().naive_utc()));
+    completed.update(db).await?;
+
+    Ok(())
+}
+
+/// Writes the files and manifests of the export to `path`, returning how
+/// many assets it holds
+async fn write_archive(
+    db: &DatabaseConnection,
+    state: &AppState,
+    job: &export_job::Model,
+    client: &client::Model,
+    folders: &HashMap<i64, folder::Model>,
+    assets: Vec<asset::Model>,
+    path: &Path,
+) -> Result<usize> {
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut manifest_assets = Vec::with_capacity(assets.len());
+    for asset in assets {
+        let folder = folders.get(&asset.folder_id);
+        let folder_uuid = folder.map(|item| item.uuid.to_string()).unwrap_or_default();
+        let extension = mime_guess::get_mime_extensions_str(&asset.content_type)
+            .and_then(|extensions| extensions.first())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default();
+        let file = format!("files/{}/{}{}", folder_uuid, asset.uuid, extension);
+
+        zip.start_file(file.as_str(), options)?;
+        let sha256 = if asset.encrypted_data_key.is_some() {
+            // The whole ciphertext is authenticated, so it is decrypted in memory
+            let content = fetch_asset_content(db, state, &asset).await?;
+            zip.write_all(&content)?;
+            Sha256::digest(&content)
+        } else {
+            let mut response = Pinata::stream_file(state, &asset.ipfs_hash).await?;
+            let mut hasher = Sha256::new();
+            while let Some(chunk) = response.chunk().await? {
+                hasher.update(&chunk);
+                zip.write_all(&chunk)?;
+            }
+            hasher.finalize()
+        };
+
+        manifest_assets.push(ManifestAsset {
+            uuid: asset.uuid.to_string(),
+            name: asset.name,
+            description: asset.description,
+            folder_uuid,
+            folder_name: folder.map(|item| item.name.clone()).unwrap_or_default(),
+            file,
+            content_type: asset.content_type,
+            size_mb: asset.size_mb,
+            sha256: hex::encode(sha256),
+            ipfs_hash: asset.ipfs_hash,
+            nft_id: asset.nft_id,
+            collection_id: asset.folder_id,
+            date_added: asset.date_added.to_string(),
+            last_updated: asset.last_updated.to_string(),
+        });
+    }
+
+    let mut csv_writer = csv::Writer::from_writer(vec![]);
+    for item in &manifest_assets {
+        csv_writer.serialize(item)?;
+    }
+    let csv_content = csv_writer
+        .into_inner()
+        .map_err(|err| Error::new(err.to_string()))?;
+
+    let asset_count = manifest_assets.len();
+    let manifest = ExportManifest {
+        export_uuid: job.uuid.to_string(),
+        client_uuid: client.uuid.to_string(),
+        folder_uuid: job
+            .folder_id
+            .and_then(|id| folders.get(&id))
+            .map(|item| item.uuid.to_string()),
+        generated_at: Utc::now().naive_utc().to_string(),
+        asset_count,
+        assets: manifest_assets,
+    };
+
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.start_file("manifest.csv", options)?;
+    zip.write_all(&csv_content)?;
+    zip.finish()?;
+
+    Ok(asset_count)
+}
+
+/// Deletes the archives of exports completed more than
+/// `EXPORT_RETENTION_HOURS` ago, marking their jobs expired
+pub async fn expire_exports(db: &DatabaseConnection, state: &AppState) -> Result<u64> {
+    let retention_hours = state.env.export_retention_hours;
+    if retention_hours == 0 {
+        return Ok(0);
+    }
+    let cutoff = Utc::now().naive_utc() - Duration::hours(retention_hours as i64);
+    let jobs = export_job::Entity::find()
+        .filter(export_job::Column::Status.eq(ExportJobStatus::Completed.as_str()))
+        .filter(export_job::Column::CompletedAt.lt(cutoff))
+        .all(db)
+        .await?;
+
+    let expired = jobs.len() as u64;
+    for job in jobs {
+        if let Some(file_path) = &job.file_path {
+            match fs::remove_file(file_path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        let mut job: export_job::ActiveModel = job.into();
+        job.status = Set(ExportJobStatus::Expired.as_str().to_string());
+        job.file_path = Set(None);
+        job.update(db).await?;
+    }
+    Ok(expired)
+}
@@ -1,3 +1,122 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use async_graphql::*;
+
+use crate::config::{settings::ContentTypePolicy, state::AppState};
+
+/// Bytes read from the start of a file to detect its type. Office documents
+/// are ZIP archives and need more than the first few magic bytes.
+const SNIFF_LENGTH: u64 = 64 * 1024;
+const OCTET_STREAM: &str = "application/octet-stream";
+
 pub fn bytes_to_mb(bytes: u64) -> f64 {
     bytes as f64 / 1024.0 / 1024.0
 }
+
+pub struct SniffedContentType {
+    /// Type to store on the asset, chosen by `CONTENT_TYPE_MISMATCH_POLICY`
+    pub content_type: String,
+    /// Type detected from the file's magic numbers
+    pub detected: String,
+}
+
+/// Detects the type of `content` from its bytes and reconciles it with the
+/// client's declared type. The declared type is only trusted for text
+/// formats, which have no magic numbers. The file is rewound afterwards.
+pub fn sniff_content_type(
+    state: &AppState,
+    declared: Option<&str>,
+    content: &mut std::fs::File,
+) -> Result<SniffedContentType> {
+    let mut head = Vec::new();
+    content.seek(SeekFrom::Start(0))?;
+    content.by_ref().take(SNIFF_LENGTH).read_to_end(&mut head)?;
+    content.seek(SeekFrom::Start(0))?;
+
+    let declared = declared
+        .map(essence)
+        .filter(|value| !value.is_empty() && value != OCTET_STREAM);
+
+    let detected = match infer::get(&head) {
+        Some(kind) => kind.mime_type().to_string(),
+        None if is_text(&head) => match &declared {
+            Some(declared) if is_text_type(declared) => declared.clone(),
+            _ => String::from("text/plain"),
+        },
+        None => String::from(OCTET_STREAM),
+    };
+
+    let declared = match declared {
+        Some(declared) => declared,
+        None => {
+            return Ok(SniffedContentType {
+                content_type: detected.clone(),
+                detected,
+            })
+        }
+    };
+
+    // Subtypes have too many aliases (image/jpg, audio/x-wav) to compare reliably
+    if primary_type(&declared) == primary_type(&detected) {
+        return Ok(SniffedContentType {
+            content_type: declared,
+            detected,
+        });
+    }
+
+    match state.env.content_type_policy {
+        ContentTypePolicy::Reject => Err(Error::new(format!(
+            "The file was uploaded as {} but its content is {}",
+            declared, detected
+        ))),
+        ContentTypePolicy::Warn => {
+            tracing::warn!(
+                "Upload declared as {} looks like {}, keeping the declared type",
+                declared,
+                detected
+            );
+            Ok(SniffedContentType {
+                content_type: declared,
+                detected,
+            })
+        }
+        ContentTypePolicy::Override => Ok(SniffedContentType {
+            content_type: detected.clone(),
+            detected,
+        }),
+    }
+}
+
+fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+fn primary_type(content_type: &str) -> &str {
+    content_type.split('/').next().unwrap_or_default()
+}
+
+fn is_text_type(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type.ends_with("+xml")
+        || content_type.ends_with("+json")
+        || matches!(
+            content_type,
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+/// UTF-8 without NUL bytes, allowing for a character cut off at the end
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
+}
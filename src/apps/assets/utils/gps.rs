@@ -0,0 +1,442 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use async_graphql::*;
+use exif::{experimental::Writer, Context, Exif, In, Tag};
+
+/// Prefix of the EXIF block inside a JPEG APP1 segment or a WebP chunk
+const EXIF_ID: &[u8] = b"Exif\0\0";
+/// Prefix of the XMP packet inside a JPEG APP1 segment
+const XMP_ID: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Keyword of the PNG text chunk that carries XMP
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// VP8X flags announcing EXIF and XMP chunks
+const WEBP_EXIF_FLAG: u8 = 0x08;
+const WEBP_XMP_FLAG: u8 = 0x04;
+
+/// Removes GPS coordinates from the image in `content` so they never reach
+/// IPFS: the EXIF block is rewritten without its GPS directory and XMP
+/// packets that mention GPS are dropped. Images without coordinates are left
+/// untouched, and formats that cannot be rewritten are refused when they
+/// carry any. The file is rewound afterwards.
+pub fn strip_gps(content_type: &str, content: &mut std::fs::File) -> Result<()> {
+    content.seek(SeekFrom::Start(0))?;
+    let mut bytes = Vec::new();
+    content.read_to_end(&mut bytes)?;
+
+    let stripped = match content_type {
+        "image/jpeg" => strip_jpeg(&bytes)?,
+        "image/png" => strip_png(&bytes)?,
+        "image/webp" => strip_webp(&bytes)?,
+        _ => {
+            let exif = exif::Reader::new().read_from_container(&mut Cursor::new(&bytes));
+            if let Ok(exif) = exif {
+                if has_gps(&exif) {
+                    return Err(Error::new(format!(
+                        "GPS coordinates cannot be removed from {} files",
+                        content_type
+                    )));
+                }
+            }
+            None
+        }
+    };
+
+    if let Some(stripped) = stripped {
+        content.set_len(0)?;
+        content.seek(SeekFrom::Start(0))?;
+        content.write_all(&stripped)?;
+    }
+    content.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+fn has_gps(exif: &Exif) -> bool {
+    exif.fields()
+        .any(|field| field.tag.context() == Context::Gps)
+}
+
+/// Rewrites a raw TIFF EXIF block without its GPS fields. Returns `None` when
+/// there was nothing to remove and an empty block when nothing is left, or
+/// when the block cannot be read or written back, so coordinates never
+/// survive in a block that was not understood.
+fn tiff_without_gps(tiff: &[u8]) -> Option<Vec<u8>> {
+    let exif = match exif::Reader::new().read_raw(tiff.to_vec()) {
+        Ok(exif) => exif,
+        Err(err) => {
+            tracing::warn!("Dropping unreadable EXIF block: {}", err);
+            return Some(Vec::new());
+        }
+    };
+    if !has_gps(&exif) {
+        return None;
+    }
+
+    let mut writer = Writer::new();
+    for field in exif.fields() {
+        if field.tag.context() != Context::Gps {
+            writer.push_field(field);
+        }
+    }
+    if let Some(thumbnail) = thumbnail_jpeg(&exif) {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let mut rewritten = Cursor::new(Vec::new());
+    match writer.write(&mut rewritten, exif.little_endian()) {
+        Ok(()) => Some(rewritten.into_inner()),
+        Err(err) => {
+            tracing::warn!("Dropping EXIF block that cannot be rewritten: {}", err);
+            Some(Vec::new())
+        }
+    }
+}
+
+/// The JPEG thumbnail embedded in the second IFD, which the writer only keeps
+/// when it is handed over again
+fn thumbnail_jpeg(exif: &Exif) -> Option<&[u8]> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    exif.buf().get(offset..offset.checked_add(length)?)
+}
+
+fn mentions_gps(packet: &[u8]) -> bool {
+    packet.windows(3).any(|window| window == b"GPS")
+}
+
+fn broken(format: &str) -> Error {
+    Error::new(format!(
+        "Unable to remove GPS coordinates from a broken {} file",
+        format
+    ))
+}
+
+/// Walks the JPEG segments up to the image data, rewriting the EXIF APP1
+/// segment and dropping XMP segments with GPS
+fn strip_jpeg(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err(broken("JPEG"));
+    }
+
+    let mut stripped = bytes[..2].to_vec();
+    let mut changed = false;
+    let mut pos = 2;
+    loop {
+        if pos + 2 > bytes.len() || bytes[pos] != 0xFF {
+            return Err(broken("JPEG"));
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            // Fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan or end of image: the rest holds no metadata
+            0xDA | 0xD9 => {
+                stripped.extend_from_slice(&bytes[pos..]);
+                break;
+            }
+            // Markers without a payload
+            0x01 | 0xD0..=0xD7 => {
+                stripped.extend_from_slice(&bytes[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        if pos + 4 > bytes.len() {
+            return Err(broken("JPEG"));
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > bytes.len() {
+            return Err(broken("JPEG"));
+        }
+        let payload = &bytes[pos + 4..end];
+
+        if marker == 0xE1 && payload.starts_with(EXIF_ID) {
+            if let Some(tiff) = tiff_without_gps(&payload[EXIF_ID.len()..]) {
+                changed = true;
+                if !tiff.is_empty() {
+                    let length = u16::try_from(2 + EXIF_ID.len() + tiff.len())
+                        .map_err(|_| Error::new("The EXIF block is too large to rewrite"))?;
+                    stripped.extend_from_slice(&[0xFF, 0xE1]);
+                    stripped.extend_from_slice(&length.to_be_bytes());
+                    stripped.extend_from_slice(EXIF_ID);
+                    stripped.extend_from_slice(&tiff);
+                }
+                pos = end;
+                continue;
+            }
+        } else if marker == 0xE1 && payload.starts_with(XMP_ID) && mentions_gps(payload) {
+            changed = true;
+            pos = end;
+            continue;
+        }
+
+        stripped.extend_from_slice(&bytes[pos..end]);
+        pos = end;
+    }
+
+    Ok(changed.then_some(stripped))
+}
+
+/// Rewrites the eXIf chunk of a PNG and drops XMP text chunks with GPS
+fn strip_png(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(broken("PNG"));
+    }
+
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut changed = false;
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < bytes.len() {
+        if pos + 8 > bytes.len() {
+            return Err(broken("PNG"));
+        }
+        let length =
+            u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+                as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let end = pos + 12 + length;
+        if end > bytes.len() {
+            return Err(broken("PNG"));
+        }
+        let data = &bytes[pos + 8..pos + 8 + length];
+
+        if kind == b"eXIf" {
+            if let Some(tiff) = tiff_without_gps(data) {
+                changed = true;
+                if !tiff.is_empty() {
+                    let length = u32::try_from(tiff.len())
+                        .map_err(|_| Error::new("The EXIF block is too large to rewrite"))?;
+                    let mut crc = crc32fast::Hasher::new();
+                    crc.update(kind);
+                    crc.update(&tiff);
+                    stripped.extend_from_slice(&length.to_be_bytes());
+                    stripped.extend_from_slice(kind);
+                    stripped.extend_from_slice(&tiff);
+                    stripped.extend_from_slice(&crc.finalize().to_be_bytes());
+                }
+                pos = end;
+                continue;
+            }
+        } else if kind == b"iTXt" && data.starts_with(PNG_XMP_KEYWORD) && mentions_gps(data) {
+            changed = true;
+            pos = end;
+            continue;
+        }
+
+        stripped.extend_from_slice(&bytes[pos..end]);
+        pos = end;
+        if kind == b"IEND" {
+            break;
+        }
+    }
+
+    Ok(changed.then_some(stripped))
+}
+
+/// Rewrites the EXIF chunk of a WebP and drops an XMP chunk with GPS,
+/// keeping the VP8X flags and the RIFF size in line
+fn strip_webp(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return Err(broken("WebP"));
+    }
+
+    let mut stripped = bytes[..12].to_vec();
+    let mut changed = false;
+    let mut vp8x = None;
+    let mut cleared_flags = 0;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind = &bytes[pos..pos + 4];
+        let length = u32::from_le_bytes([
+            bytes[pos + 4],
+            bytes[pos + 5],
+            bytes[pos + 6],
+            bytes[pos + 7],
+        ]) as usize;
+        let data_end = pos + 8 + length;
+        if data_end > bytes.len() {
+            return Err(broken("WebP"));
+        }
+        // Chunks are padded to an even size
+        let end = (data_end + length % 2).min(bytes.len());
+        let data = &bytes[pos + 8..data_end];
+
+        if kind == b"EXIF" {
+            // Some encoders keep the JPEG prefix in front of the TIFF data
+            let prefix = if data.starts_with(EXIF_ID) {
+                EXIF_ID
+            } else {
+                &[]
+            };
+            if let Some(tiff) = tiff_without_gps(&data[prefix.len()..]) {
+                changed = true;
+                if tiff.is_empty() {
+                    cleared_flags |= WEBP_EXIF_FLAG;
+                } else {
+                    let length = u32::try_from(prefix.len() + tiff.len())
+                        .map_err(|_| Error::new("The EXIF block is too large to rewrite"))?;
+                    stripped.extend_from_slice(kind);
+                    stripped.extend_from_slice(&length.to_le_bytes());
+                    stripped.extend_from_slice(prefix);
+                    stripped.extend_from_slice(&tiff);
+                    if length % 2 == 1 {
+                        stripped.push(0);
+                    }
+                }
+                pos = end;
+                continue;
+            }
+        } else if kind == b"XMP " && mentions_gps(data) {
+            changed = true;
+            cleared_flags |= WEBP_XMP_FLAG;
+            pos = end;
+            continue;
+        } else if kind == b"VP8X" && length > 0 {
+            vp8x = Some(stripped.len() + 8);
+        }
+
+        stripped.extend_from_slice(&bytes[pos..end]);
+        pos = end;
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+    if let Some(flags) = vp8x {
+        stripped[flags] &= !cleared_flags;
+    }
+    let size = u32::try_from(stripped.len() - 8).map_err(|_| broken("WebP"))?;
+    stripped[4..8].copy_from_slice(&size.to_le_bytes());
+    Ok(Some(stripped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use exif::{Field, Rational, Value};
+
+    use crate::apps::assets::utils::{metadata::AssetMetadata, uploads::prepare_content};
+
+    /// A small JPEG whose EXIF block carries a camera model and coordinates
+    fn jpeg_with_gps() -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(16, 16, image::Rgb([200, 80, 40]));
+        let mut encoded = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut encoded, image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let encoded = encoded.into_inner();
+
+        let model = Field {
+            tag: Tag::Model,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Test Camera".to_vec()]),
+        };
+        let latitude_ref = Field {
+            tag: Tag::GPSLatitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"S".to_vec()]),
+        };
+        let latitude = Field {
+            tag: Tag::GPSLatitude,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![
+                Rational { num: 1, denom: 1 },
+                Rational { num: 17, denom: 1 },
+                Rational { num: 30, denom: 1 },
+            ]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&model);
+        writer.push_field(&latitude_ref);
+        writer.push_field(&latitude);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut jpeg = encoded[..2].to_vec();
+        jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        jpeg.extend_from_slice(&((2 + EXIF_ID.len() + tiff.len()) as u16).to_be_bytes());
+        jpeg.extend_from_slice(EXIF_ID);
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&encoded[2..]);
+        jpeg
+    }
+
+    fn read_exif(bytes: &[u8]) -> Exif {
+        exif::Reader::new()
+            .read_from_container(&mut Cursor::new(bytes))
+            .unwrap()
+    }
+
+    /// Tags of the first IFD as stored, pointer tags included
+    fn primary_tags(exif: &Exif) -> Vec<u16> {
+        let tiff = exif.buf();
+        let read_u16 = |at: usize| {
+            let bytes = [tiff[at], tiff[at + 1]];
+            if exif.little_endian() {
+                u16::from_le_bytes(bytes)
+            } else {
+                u16::from_be_bytes(bytes)
+            }
+        };
+        let offset = if exif.little_endian() {
+            u32::from_le_bytes([tiff[4], tiff[5], tiff[6], tiff[7]])
+        } else {
+            u32::from_be_bytes([tiff[4], tiff[5], tiff[6], tiff[7]])
+        } as usize;
+        (0..read_u16(offset) as usize)
+            .map(|entry| read_u16(offset + 2 + entry * 12))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn uploads_are_pinned_without_the_gps_directory() {
+        let jpeg = jpeg_with_gps();
+        let exif = read_exif(&jpeg);
+        assert!(has_gps(&exif));
+        assert!(primary_tags(&exif).contains(&Tag::GPSInfoIFDPointer.number()));
+
+        let mut content = tempfile::tempfile().unwrap();
+        content.write_all(&jpeg).unwrap();
+        let prepared = prepare_content(content, "image/jpeg", true, true)
+            .await
+            .unwrap();
+        let mut pinned = Vec::new();
+        let mut content = prepared.content;
+        content.read_to_end(&mut pinned).unwrap();
+
+        let exif = read_exif(&pinned);
+        assert!(!has_gps(&exif));
+        assert!(!primary_tags(&exif).contains(&Tag::GPSInfoIFDPointer.number()));
+        assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+        image::load_from_memory(&pinned).unwrap();
+
+        let metadata = AssetMetadata::from_json(prepared.metadata.unwrap()).unwrap();
+        assert!(metadata.gps_stripped);
+        assert!(metadata.exif.iter().any(|field| field.tag == "Model"));
+        assert!(!metadata
+            .exif
+            .iter()
+            .any(|field| field.tag.starts_with("GPS")));
+    }
+
+    #[test]
+    fn leaves_images_without_gps_untouched() {
+        let jpeg = jpeg_with_gps();
+        let once = strip_jpeg(&jpeg).unwrap().unwrap();
+        assert!(strip_jpeg(&once).unwrap().is_none());
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use async_graphql::*;
+use lopdf::{Document, Object};
+use serde::{Deserialize, Serialize};
+use symphonia::core::{
+    codecs::CODEC_TYPE_NULL, formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions,
+    probe::Hint,
+};
+
+/// PDF document information keys copied into the metadata
+const PDF_PROPERTIES: [&[u8]; 6] = [
+    b"Title",
+    b"Author",
+    b"Subject",
+    b"Creator",
+    b"Producer",
+    b"CreationDate",
+];
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone)]
+pub struct ExifField {
+    pub tag: String,
+    pub value: String,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone)]
+pub struct DocumentProperty {
+    pub name: String,
+    pub value: String,
+}
+
+/// Technical facts read from the file itself when it is uploaded. Only the
+/// fields that apply to the kind of file are set.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AssetMetadata {
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub exif: Vec<ExifField>,
+    pub gps_stripped: bool,
+    pub duration_seconds: Option<f64>,
+    pub codecs: Vec<String>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub page_count: Option<i32>,
+    pub document_properties: Vec<DocumentProperty>,
+}
+
+impl AssetMetadata {
+    pub fn from_json(value: serde_json::Value) -> Option<AssetMetadata> {
+        serde_json::from_value(value).ok()
+    }
+}
+
+/// Reads the metadata of `content` based on its content type. Unsupported
+/// or unreadable files yield `None`, since metadata must never block an
+/// upload. The file is rewound afterwards.
+pub fn extract_metadata(
+    content_type: &str,
+    content: &mut std::fs::File,
+    strip_gps: bool,
+) -> Option<serde_json::Value> {
+    let result = if content_type.starts_with("image") {
+        image_metadata(content, strip_gps)
+    } else if content_type.starts_with("audio") || content_type.starts_with("video") {
+        media_metadata(content_type, content)
+    } else if content_type == "application/pdf" {
+        pdf_metadata(content)
+    } else {
+        return None;
+    };
+
+    let rewound = content.seek(SeekFrom::Start(0));
+    match (result, rewound) {
+        (Ok(metadata), Ok(_)) => serde_json::to_value(metadata).ok(),
+        (Err(err), _) => {
+            tracing::warn!("Failed to read {} metadata: {}", content_type, err.message);
+            None
+        }
+        (_, Err(err)) => {
+            tracing::warn!("Failed to rewind upload: {}", err);
+            None
+        }
+    }
+}
+
+fn image_metadata(content: &mut std::fs::File, strip_gps: bool) -> Result<AssetMetadata> {
+    let mut metadata = AssetMetadata {
+        gps_stripped: strip_gps,
+        ..Default::default()
+    };
+
+    content.seek(SeekFrom::Start(0))?;
+    let dimensions = image::io::Reader::new(BufReader::new(&mut *content))
+        .with_guessed_format()?
+        .into_dimensions();
+    if let Ok((width, height)) = dimensions {
+        metadata.width = Some(width as i32);
+        metadata.height = Some(height as i32);
+    }
+
+    content.seek(SeekFrom::Start(0))?;
+    // Most images carry no EXIF at all, so a missing block is not an error
+    if let Ok(exif) = exif::Reader::new().read_from_container(&mut BufReader::new(&mut *content)) {
+        for field in exif.fields() {
+            if field.ifd_num != exif::In::PRIMARY {
+                continue;
+            }
+            metadata.exif.push(ExifField {
+                tag: field.tag.to_string(),
+                value: field.display_value().with_unit(&exif).to_string(),
+            });
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Reads duration and codecs from the container headers without decoding
+fn media_metadata(content_type: &str, content: &mut std::fs::File) -> Result<AssetMetadata> {
+    content.seek(SeekFrom::Start(0))?;
+    let source = MediaSourceStream::new(Box::new(content.try_clone()?), Default::default());
+    let mut hint = Hint::new();
+    hint.mime_type(content_type);
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| Error::new(err.to_string()))?;
+
+    let mut metadata = AssetMetadata::default();
+    let codecs = symphonia::default::get_codecs();
+    for track in probed.format.tracks() {
+        let params = &track.codec_params;
+        if let (Some(frames), Some(time_base)) = (params.n_frames, params.time_base) {
+            let time = time_base.calc_time(frames);
+            let seconds = time.seconds as f64 + time.frac;
+            if metadata
+                .duration_seconds
+                .is_none_or(|current| seconds > current)
+            {
+                metadata.duration_seconds = Some(seconds);
+            }
+        }
+        if params.codec != CODEC_TYPE_NULL {
+            if let Some(codec) = codecs.get_codec(params.codec) {
+                metadata.codecs.push(codec.short_name.to_string());
+            }
+        }
+        if metadata.sample_rate.is_none() {
+            metadata.sample_rate = params.sample_rate.map(|rate| rate as i32);
+        }
+        if metadata.channels.is_none() {
+            metadata.channels = params.channels.map(|channels| channels.count() as i32);
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn pdf_metadata(content: &mut std::fs::File) -> Result<AssetMetadata> {
+    content.seek(SeekFrom::Start(0))?;
+    let mut bytes = Vec::new();
+    content.read_to_end(&mut bytes)?;
+    let document = Document::load_mem(&bytes).map_err(|err| Error::new(err.to_string()))?;
+
+    let mut metadata = AssetMetadata {
+        page_count: Some(document.get_pages().len() as i32),
+        ..Default::default()
+    };
+
+    let info = document
+        .trailer
+        .get(b"Info")
+        .and_then(Object::as_reference)
+        .and_then(|id| document.get_dictionary(id));
+    if let Ok(info) = info {
+        for name in PDF_PROPERTIES {
+            if let Ok(Object::String(value, _)) = info.get(name) {
+                if value.is_empty() {
+                    continue;
+                }
+                metadata.document_properties.push(DocumentProperty {
+                    name: String::from_utf8_lossy(name).to_string(),
+                    value: decode_pdf_string(value),
+                });
+            }
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Text strings are UTF-16BE when they start with a byte order mark and
+/// PDFDocEncoding, which matches Latin-1 for printable text, otherwise
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|byte| *byte as char).collect()
+    }
+}
@@ -1,4 +1,17 @@
+pub mod archives;
+pub mod certificates;
+pub mod chain;
 pub mod contract;
+pub mod encryption;
+pub mod exports;
 pub mod files;
 pub mod formating;
+pub mod gps;
+pub mod identity;
+pub mod metadata;
 pub mod pinata;
+pub mod qrcodes;
+pub mod quota;
+pub mod reconciliation;
+pub mod thumbnails;
+pub mod uploads;
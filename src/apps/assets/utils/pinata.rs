@@ -1,43 +1,66 @@
-use std::io;
+use std::io::{self, Seek, SeekFrom, Write};
 
 use async_graphql::{Error, Result};
-use pinata_sdk::{PinByFile, PinataApi, PinnedObject};
+use pinata_sdk::{PinByFile, PinnedObject};
 use tempfile::NamedTempFile;
 
-use crate::config::settings::ENV;
+use crate::config::{metrics::observe_call, state::AppState};
 
 pub struct Pinata;
 
 impl Pinata {
-    fn init() -> Result<PinataApi> {
-        let env = ENV::init();
-        let pinata_api_key = env.pinata_api_key;
-        let pinata_api_secret = env.pinata_api_secret;
-        let api = PinataApi::new(pinata_api_key, pinata_api_secret)?;
-        return Ok(api);
-    }
-
-    pub async fn pin_file(file: std::fs::File) -> Result<PinnedObject> {
-        let api = Pinata::init()?;
+    pub async fn pin_file(state: &AppState, file: std::fs::File) -> Result<PinnedObject> {
+        let api = state.pinata()?;
         let mut temp_file = NamedTempFile::new()?;
         io::copy(&mut &file, &mut temp_file)?;
         let temp_file_path = temp_file.path().to_str();
         if let Some(file_path) = temp_file_path {
-            let response = api.pin_file(PinByFile::new(file_path)).await?;
+            let response = observe_call(
+                "pinata",
+                "pin_file",
+                api.pin_file(PinByFile::new(file_path)),
+            )
+            .await?;
             Ok(response)
         } else {
             Err(Error::new("Failed to pin file"))
         }
     }
 
-    pub async fn unpin_file(hash: &str) -> Result<()> {
-        let api = Pinata::init()?;
-        api.unpin(hash).await?;
+    pub async fn pin_bytes(state: &AppState, bytes: &[u8]) -> Result<PinnedObject> {
+        let mut file = tempfile::tempfile()?;
+        file.write_all(bytes)?;
+        file.seek(SeekFrom::Start(0))?;
+        Pinata::pin_file(state, file).await
+    }
+
+    pub async fn unpin_file(state: &AppState, hash: &str) -> Result<()> {
+        let api = state.pinata()?;
+        observe_call("pinata", "unpin", api.unpin(hash)).await?;
         Ok(())
     }
 
-    pub fn build_url(hash: String) -> String {
-        let pinata_ipfs_gateway = ENV::init().pinata_ipfs_gateway;
-        format!("https://{}/ips/{}", pinata_ipfs_gateway, hash)
+    pub async fn fetch_file(state: &AppState, hash: &str) -> Result<Vec<u8>> {
+        let response = Pinata::stream_file(state, hash).await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Requests the file from the gateway, for callers reading the body in
+    /// chunks rather than holding it whole
+    pub async fn stream_file(state: &AppState, hash: &str) -> Result<reqwest::Response> {
+        let request = async {
+            state
+                .http
+                .get(Pinata::build_url(state, hash.to_string()))
+                .send()
+                .await?
+                .error_for_status()
+        };
+        Ok(observe_call("pinata", "fetch_file", request).await?)
+    }
+
+    pub fn build_url(state: &AppState, hash: String) -> String {
+        let pinata_ipfs_gateway = &state.env.pinata_ipfs_gateway;
+        format!("https://{}/ipfs/{}", pinata_ipfs_gateway, hash)
     }
 }
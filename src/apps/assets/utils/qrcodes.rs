@@ -0,0 +1,55 @@
+use std::io::Cursor;
+
+use async_graphql::*;
+use image::{GrayImage, ImageFormat, Luma};
+use qrcode::{render::svg, Color, QrCode};
+
+pub const DEFAULT_QR_CODE_SIZE: u32 = 256;
+pub const MIN_QR_CODE_SIZE: u32 = 64;
+pub const MAX_QR_CODE_SIZE: u32 = 2048;
+
+/// Modules of white space around the code, as recommended by the QR spec
+const QUIET_ZONE: u32 = 4;
+
+pub fn qr_code_size(size: Option<u32>) -> u32 {
+    size.unwrap_or(DEFAULT_QR_CODE_SIZE)
+        .clamp(MIN_QR_CODE_SIZE, MAX_QR_CODE_SIZE)
+}
+
+/// Renders the code as a square image of at least `size` pixels
+pub fn qr_code_image(data: &str, size: u32) -> Result<GrayImage> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| Error::new(err.to_string()))?;
+    let width = code.width() as u32;
+    let modules = width + QUIET_ZONE * 2;
+    let scale = size.div_ceil(modules).max(1);
+    let colors = code.to_colors();
+
+    let mut image = GrayImage::from_pixel(modules * scale, modules * scale, Luma([255]));
+    for (index, color) in colors.iter().enumerate() {
+        if *color == Color::Dark {
+            let x = (index as u32 % width + QUIET_ZONE) * scale;
+            let y = (index as u32 / width + QUIET_ZONE) * scale;
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    image.put_pixel(x + dx, y + dy, Luma([0]));
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+pub fn qr_code_png(data: &str, size: u32) -> Result<Vec<u8>> {
+    let image = qr_code_image(data, size)?;
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+pub fn qr_code_svg(data: &str, size: u32) -> Result<String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| Error::new(err.to_string()))?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(size, size)
+        .build())
+}
@@ -0,0 +1,277 @@
+use async_graphql::*;
+use entity::entities::{
+    asset, client, client_package_subscription, client_usage, folder, subscription_package, user,
+};
+use sea_orm::{
+    entity::*, DatabaseConnection, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QuerySelect,
+};
+use uuid::Uuid;
+
+use crate::config::{metrics::record_quota_rejection, telemetry::record_client};
+
+pub struct ClientQuota {
+    pub client: client::Model,
+    pub package: subscription_package::Model,
+    pub usage: client_usage::Model,
+}
+
+impl ClientQuota {
+    pub async fn for_user(user: &user::Model, db: &DatabaseConnection) -> Result<ClientQuota> {
+        let user_client = client::Entity::find()
+            .filter(client::Column::UserId.eq(user.id))
+            .one(db)
+            .await?;
+
+        if user_client.is_none() {
+            return Err(Error::new("User Client not found"));
+        }
+
+        let client_package = client::Entity::find()
+            .filter(client::Column::UserId.eq(user.id))
+            .join(
+                JoinType::InnerJoin,
+                client::Relation::ClientPackageSubscription.def(),
+            )
+            .join(
+                JoinType::InnerJoin,
+                client_package_subscription::Relation::SubscriptionPackage.def(),
+            )
+            .select_also(subscription_package::Entity)
+            .one(db)
+            .await?;
+
+        if let Some((client, package)) = client_package {
+            let usage = match client_usage::Entity::find()
+                .filter(client_usage::Column::ClientId.eq(client.id))
+                .one(db)
+                .await?
+            {
+                Some(usage) => usage,
+                None => {
+                    client_usage::ActiveModel {
+                        uuid: Set(Uuid::new_v4()),
+                        client_id: Set(client.id.into()),
+                        used_storage_mb: Set(0.0),
+                        active_sessions: Set(0),
+                        ..Default::default()
+                    }
+                    .insert(db)
+                    .await?
+                }
+            };
+
+            if let Some(package) = package {
+                record_client(client.id);
+                Ok(ClientQuota {
+                    client,
+                    package,
+                    usage,
+                })
+            } else {
+                Err(Error::new(
+                    "You do not currently have an active subscription",
+                ))
+            }
+        } else {
+            Err(Error::new("You are not authorized to perform this action"))
+        }
+    }
+
+    pub fn policy(&self) -> Result<UploadPolicy> {
+        UploadPolicy::try_from(&self.package)
+    }
+
+    /// Checks that `added_mb` more storage fits in the package once `freed_mb`
+    /// (e.g. the size of a file being replaced) has been released.
+    pub fn check_storage(&self, added_mb: f64, freed_mb: f64) -> Result<()> {
+        let new_used_storage_mb = self.usage.used_storage_mb - freed_mb + added_mb;
+        if new_used_storage_mb > self.package.storage_capacity_mb {
+            record_quota_rejection("storage");
+            return Err(Error::new(format!(
+                "Insuficient storage: Uploading file of {}mb will exceed your maximum storage of {}mb.",
+                added_mb, self.package.storage_capacity_mb
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Upload limits of a subscription package. Limits that are not set are
+/// unlimited, and so is everything for clients without a package.
+#[derive(Default)]
+pub struct UploadPolicy {
+    pub max_file_size_mb: Option<f64>,
+    /// Content types or categories (e.g. `image`, `video/mp4`) that may be uploaded
+    pub allowed_content_types: Option<Vec<String>>,
+    /// Content types or categories that may not be uploaded
+    pub denied_content_types: Vec<String>,
+    pub max_assets_per_folder: Option<i32>,
+    pub max_folders: Option<i32>,
+}
+
+impl TryFrom<&subscription_package::Model> for UploadPolicy {
+    type Error = Error;
+
+    fn try_from(value: &subscription_package::Model) -> Result<Self> {
+        Ok(Self {
+            max_file_size_mb: value.max_file_size_mb,
+            allowed_content_types: value
+                .allowed_content_types
+                .clone()
+                .map(|types| content_type_list(value, types))
+                .transpose()?,
+            denied_content_types: value
+                .denied_content_types
+                .clone()
+                .map(|types| content_type_list(value, types))
+                .transpose()?
+                .unwrap_or_default(),
+            max_assets_per_folder: value.max_assets_per_folder,
+            max_folders: value.max_folders,
+        })
+    }
+}
+
+impl UploadPolicy {
+    pub async fn for_client(db: &DatabaseConnection, client_id: i32) -> Result<UploadPolicy> {
+        let package = client_package_subscription::Entity::find()
+            .filter(client_package_subscription::Column::ClientId.eq(client_id as i64))
+            .find_also_related(subscription_package::Entity)
+            .one(db)
+            .await?;
+
+        if let Some((_, Some(package))) = package {
+            UploadPolicy::try_from(&package)
+        } else {
+            Ok(UploadPolicy::default())
+        }
+    }
+
+    pub fn check_file_size(&self, size_mb: f64) -> Result<()> {
+        if let Some(max_file_size_mb) = self.max_file_size_mb {
+            if size_mb > max_file_size_mb {
+                record_quota_rejection("file_size");
+                return Err(Error::new(format!(
+                    "File of {:.2}mb exceeds your package's maximum file size of {}mb",
+                    size_mb, max_file_size_mb
+                ))
+                .extend_with(|_, ext| {
+                    ext.set("code", "FILE_TOO_LARGE");
+                    ext.set("limit", max_file_size_mb);
+                    ext.set("actual", size_mb);
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_content_type(&self, content_type: &str) -> Result<()> {
+        let denied = self
+            .denied_content_types
+            .iter()
+            .any(|pattern| content_type_matches(content_type, pattern));
+        let allowed = match &self.allowed_content_types {
+            Some(allowed) => allowed
+                .iter()
+                .any(|pattern| content_type_matches(content_type, pattern)),
+            None => true,
+        };
+
+        if denied || !allowed {
+            let content_type = content_type.to_string();
+            record_quota_rejection("content_type");
+            return Err(Error::new(format!(
+                "Your package does not allow uploading {} files",
+                content_type
+            ))
+            .extend_with(|_, ext| {
+                ext.set("code", "CONTENT_TYPE_NOT_ALLOWED");
+                ext.set("contentType", content_type.as_str());
+            }));
+        }
+        Ok(())
+    }
+
+    /// Checks that `added` more assets fit in the folder
+    pub async fn check_folder_assets(
+        &self,
+        db: &DatabaseConnection,
+        folder: &folder::Model,
+        added: u64,
+    ) -> Result<()> {
+        if let Some(max_assets) = self.max_assets_per_folder {
+            let count = asset::Entity::find()
+                .filter(asset::Column::FolderId.eq(folder.id as i64))
+                .count(db)
+                .await?;
+            if count + added > max_assets as u64 {
+                let folder_uuid = folder.uuid.to_string();
+                record_quota_rejection("folder_assets");
+                return Err(Error::new(format!(
+                    "Folder {} can hold at most {} assets on your package",
+                    folder.name, max_assets
+                ))
+                .extend_with(|_, ext| {
+                    ext.set("code", "FOLDER_ASSET_LIMIT_REACHED");
+                    ext.set("limit", max_assets);
+                    ext.set("folderUuid", folder_uuid.as_str());
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `added` more folders fit in the client's account
+    pub async fn check_folders(
+        &self,
+        db: &DatabaseConnection,
+        client_id: i64,
+        added: u64,
+    ) -> Result<()> {
+        if let Some(max_folders) = self.max_folders {
+            let count = folder::Entity::find()
+                .filter(folder::Column::ClientId.eq(client_id))
+                .count(db)
+                .await?;
+            if count + added > max_folders as u64 {
+                record_quota_rejection("folders");
+                return Err(Error::new(format!(
+                    "Your package allows at most {} folders",
+                    max_folders
+                ))
+                .extend_with(|_, ext| {
+                    ext.set("code", "FOLDER_LIMIT_REACHED");
+                    ext.set("limit", max_folders);
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Refuses uploads when a package's list is not an array of strings, rather
+/// than dropping the restriction
+fn content_type_list(
+    package: &subscription_package::Model,
+    value: serde_json::Value,
+) -> Result<Vec<String>> {
+    serde_json::from_value(value).map_err(|err| {
+        tracing::error!(
+            package_id = package.id,
+            error = %err,
+            "Invalid content types in subscription package"
+        );
+        Error::new("The upload policy of your package is invalid, please contact support")
+    })
+}
+
+/// `image` and `image/*` match every image type, anything else must match exactly
+fn content_type_matches(content_type: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    let category = pattern.trim_end_matches("/*");
+    if category.contains('/') {
+        content_type == category
+    } else {
+        content_type.split('/').next() == Some(category)
+    }
+}
@@ -0,0 +1,571 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use async_graphql::*;
+use chrono::Utc;
+use entity::entities::{asset, folder, reconciliation_issue, reconciliation_job};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use uuid::Uuid;
+
+use candid::Principal;
+
+use super::contract::{
+    parse_principal, BurnNFTResult, Contract, MintNFTResult, NFTDetails, TokenId,
+};
+use crate::config::state::AppState;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ReconciliationJobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl ReconciliationJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReconciliationJobStatus::Pending => "pending",
+            ReconciliationJobStatus::Running => "running",
+            ReconciliationJobStatus::Completed => "completed",
+            ReconciliationJobStatus::Failed => "failed",
+        }
+    }
+}
+
+impl From<&str> for ReconciliationJobStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "running" => ReconciliationJobStatus::Running,
+            "completed" => ReconciliationJobStatus::Completed,
+            "failed" => ReconciliationJobStatus::Failed,
+            _ => ReconciliationJobStatus::Pending,
+        }
+    }
+}
+
+/// How an asset or token disagrees with the canister. Consistent assets are
+/// only counted on the job.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ReconciliationIssueKind {
+    /// The asset's token or its whole collection no longer exists
+    MissingOnChain,
+    /// The token exists but its metadata names another file
+    MetadataMismatch,
+    /// A token of one of our collections that no asset points to
+    OrphanedToken,
+}
+
+impl ReconciliationIssueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReconciliationIssueKind::MissingOnChain => "missing_on_chain",
+            ReconciliationIssueKind::MetadataMismatch => "metadata_mismatch",
+            ReconciliationIssueKind::OrphanedToken => "orphaned_token",
+        }
+    }
+}
+
+impl From<&str> for ReconciliationIssueKind {
+    fn from(value: &str) -> Self {
+        match value {
+            "metadata_mismatch" => ReconciliationIssueKind::MetadataMismatch,
+            "orphaned_token" => ReconciliationIssueKind::OrphanedToken,
+            _ => ReconciliationIssueKind::MissingOnChain,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum RepairAction {
+    /// Mints a new token for the asset, burning the mismatched one first
+    Remint,
+    /// Burns an orphaned token
+    BurnToken,
+    /// Marks the issue resolved without touching the canister
+    Dismiss,
+}
+
+impl RepairAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepairAction::Remint => "remint",
+            RepairAction::BurnToken => "burn_token",
+            RepairAction::Dismiss => "dismiss",
+        }
+    }
+}
+
+struct FoundIssue {
+    kind: ReconciliationIssueKind,
+    asset_id: Option<i64>,
+    collection_id: i64,
+    token_id: Option<String>,
+    detail: String,
+}
+
+/// Reconciles every asset in scope of the job, recording the failure on the
+/// job instead of returning it since this runs detached from any request
+pub async fn run_reconciliation_job(db: DatabaseConnection, state: Arc<AppState>, job_id: i32) {
+    if let Err(err) = reconcile(&db, &state, job_id).await {
+        if let Ok(Some(job)) = reconciliation_job::Entity::find_by_id(job_id)
+            .one(&db)
+            .await
+        {
+            let mut job: reconciliation_job::ActiveModel = job.into();
+            job.status = Set(ReconciliationJobStatus::Failed.as_str().to_string());
+            job.error = Set(Some(err.message));
+            job.completed_at = Set(Some(Utc::now().naive_utc()));
+            let _ = job.update(&db).await;
+        }
+    }
+}
+
+async fn reconcile(db: &DatabaseConnection, state: &AppState, job_id: i32) -> Result<()> {
+    let job = match reconciliation_job::Entity::find_by_id(job_id)
+        .one(db)
+        .await?
+    {
+        Some(job) => job,
+        None => {
+            return Err(Error::new(format!(
+                "Reconciliation job {} was not found",
+                job_id
+            )))
+        }
+    };
+    let mut running: reconciliation_job::ActiveModel = job.clone().into();
+    running.status = Set(ReconciliationJobStatus::Running.as_str().to_string());
+    running.update(db).await?;
+
+    let mut stmt = folder::Entity::find();
+    if let Some(client_id) = job.client_id {
+        stmt = stmt.filter(folder::Column::ClientId.eq(client_id));
+    }
+    let folders = stmt.order_by_asc(folder::Column::Id).all(db).await?;
+
+    let mut asset_count = 0;
+    let mut consistent_count = 0;
+    let mut issue_count = 0;
+    for folder in folders {
+        let assets = asset::Entity::find()
+            .filter(asset::Column::FolderId.eq(folder.id as i64))
+            .order_by_asc(asset::Column::Id)
+            .all(db)
+            .await?;
+        asset_count += assets.len() as i32;
+
+        let issues = reconcile_folder(state, &folder, &assets).await?;
+        consistent_count += assets.len() as i32
+            - issues
+                .iter()
+                .filter(|issue| issue.asset_id.is_some())
+                .count() as i32;
+        issue_count += issues.len() as i32;
+
+        for issue in issues {
+            let issue = reconciliation_issue::ActiveModel {
+                uuid: Set(Uuid::new_v4()),
+                job_id: Set(job.id as i64),
+                kind: Set(issue.kind.as_str().to_string()),
+                asset_id: Set(issue.asset_id),
+                collection_id: Set(issue.collection_id),
+                token_id: Set(issue.token_id),
+                detail: Set(Some(issue.detail)),
+                ..Default::default()
+            };
+            issue.insert(db).await?;
+        }
+    }
+
+    let mut completed: reconciliation_job::ActiveModel = job.into();
+    completed.status = Set(ReconciliationJobStatus::Completed.as_str().to_string());
+    completed.asset_count = Set(asset_count);
+    completed.consistent_count = Set(consistent_count);
+    completed.issue_count = Set(issue_count);
+    completed.completed_at = Set(Some(Utc::now().naive_utc()));
+    completed.update(db).await?;
+    Ok(())
+}
+
+/// Compares the folder's assets with the tokens of its collection
+async fn reconcile_folder(
+    state: &AppState,
+    folder: &folder::Model,
+    assets: &[asset::Model],
+) -> Result<Vec<FoundIssue>> {
+    let collection_id = folder.id as i64;
+    if Contract::get_collection(state, folder.id as u64)
+        .await?
+        .is_none()
+    {
+        return Ok(assets
+            .iter()
+            .map(|asset| FoundIssue {
+                kind: ReconciliationIssueKind::MissingOnChain,
+                asset_id: Some(asset.id as i64),
+                collection_id,
+                token_id: Some(TokenId::of(asset).to_string()),
+                detail: format!("Collection {} was not found", collection_id),
+            })
+            .collect());
+    }
+
+    let mut tokens: HashMap<u64, _> = Contract::get_collection_nfts(state, folder.id as u64)
+        .await?
+        .into_iter()
+        .map(|nft| (nft.id, nft))
+        .collect();
+
+    let mut issues = Vec::new();
+    for asset in assets {
+        let nft = tokens.remove(&(asset.nft_id as u64));
+        if let Some((kind, detail)) = classify_asset(asset, nft.as_ref()) {
+            issues.push(FoundIssue {
+                kind,
+                asset_id: Some(asset.id as i64),
+                collection_id,
+                token_id: Some(TokenId::of(asset).to_string()),
+                detail,
+            });
+        }
+    }
+
+    let mut orphaned: Vec<_> = tokens.into_values().collect();
+    orphaned.sort_by_key(|nft| nft.id);
+    for nft in orphaned {
+        issues.push(FoundIssue {
+            kind: ReconciliationIssueKind::OrphanedToken,
+            asset_id: None,
+            collection_id,
+            token_id: Some(nft.token_id().to_string()),
+            detail: format!("No asset points to this token, owned by {}", nft.owner),
+        });
+    }
+
+    Ok(issues)
+}
+
+/// How the asset disagrees with its token, `None` when they match
+fn classify_asset(
+    asset: &asset::Model,
+    nft: Option<&NFTDetails>,
+) -> Option<(ReconciliationIssueKind, String)> {
+    let nft = match nft {
+        Some(nft) => nft,
+        None => {
+            return Some((
+                ReconciliationIssueKind::MissingOnChain,
+                format!("Token {} was not found", TokenId::of(asset)),
+            ))
+        }
+    };
+    match nft.asset() {
+        Some(minted)
+            if minted.ipfs_hash == asset.ipfs_hash && minted.uuid == asset.uuid.to_string() =>
+        {
+            None
+        }
+        Some(minted) => Some((
+            ReconciliationIssueKind::MetadataMismatch,
+            format!(
+                "Token was minted for {} with {}, the asset has {}",
+                minted.uuid, minted.ipfs_hash, asset.ipfs_hash
+            ),
+        )),
+        None => Some((
+            ReconciliationIssueKind::MetadataMismatch,
+            "Token metadata is not an asset".to_string(),
+        )),
+    }
+}
+
+/// Checks that reminting still applies: the asset must disagree with its
+/// token the way the issue recorded, and a token still on chain must be
+/// held by the backend so it can be burned afterwards
+fn check_remint(
+    kind: ReconciliationIssueKind,
+    asset: &asset::Model,
+    nft: Option<&NFTDetails>,
+    backend: Principal,
+) -> Result<()> {
+    match classify_asset(asset, nft) {
+        None => {
+            return Err(Error::new(
+                "The asset now matches its token, dismiss the issue instead",
+            ))
+        }
+        Some((current, _)) if current != kind => {
+            return Err(Error::new(
+                "The asset changed since the issue was found, run a new reconciliation",
+            ))
+        }
+        Some(_) => {}
+    }
+    if let Some(nft) = nft {
+        if nft.owner != backend {
+            return Err(Error::new(format!(
+                "Token {} is held by {}, it must be returned before reminting",
+                nft.token_id(),
+                nft.owner
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the token is still orphaned: it must exist and no asset may
+/// have been pointed at it since the issue was found
+fn check_burn(
+    token_id: TokenId,
+    nft: Option<&NFTDetails>,
+    referenced_by: Option<&asset::Model>,
+) -> Result<()> {
+    if let Some(asset) = referenced_by {
+        return Err(Error::new(format!(
+            "Asset {} now points to token {}, it is no longer orphaned",
+            asset.uuid, token_id
+        )));
+    }
+    if nft.is_none() {
+        return Err(Error::new(format!(
+            "Token {} no longer exists, dismiss the issue instead",
+            token_id
+        )));
+    }
+    Ok(())
+}
+
+/// Applies `action` to an unresolved issue and marks it resolved. The asset
+/// or token is classified again first, so stale issues are refused.
+pub async fn repair_issue(
+    db: &DatabaseConnection,
+    state: &AppState,
+    issue: reconciliation_issue::Model,
+    action: RepairAction,
+) -> Result<reconciliation_issue::Model> {
+    if issue.resolved_at.is_some() {
+        return Err(Error::new("This issue has already been resolved"));
+    }
+    let kind = ReconciliationIssueKind::from(issue.kind.as_str());
+
+    match action {
+        RepairAction::Remint => {
+            if kind == ReconciliationIssueKind::OrphanedToken {
+                return Err(Error::new("Orphaned tokens have no asset to remint"));
+            }
+            let asset = match issue.asset_id {
+                Some(asset_id) => asset::Entity::find_by_id(asset_id as i32).one(db).await?,
+                None => None,
+            };
+            let asset = match asset {
+                Some(asset) => asset,
+                None => return Err(Error::new("The issue's asset no longer exists")),
+            };
+            let nft = Contract::get_nft(state, TokenId::of(&asset)).await?;
+            check_remint(kind, &asset, nft.as_ref(), Contract::principal(state)?)?;
+            remint_asset(db, state, asset, nft).await?;
+        }
+        RepairAction::BurnToken => {
+            if kind != ReconciliationIssueKind::OrphanedToken {
+                return Err(Error::new(
+                    "Only orphaned tokens can be burned, remint assets instead",
+                ));
+            }
+            if let Some(token_id) = &issue.token_id {
+                let token_id = TokenId::from_str(token_id)?;
+                let referenced_by = asset::Entity::find()
+                    .filter(asset::Column::FolderId.eq(token_id.collection_id as i64))
+                    .filter(asset::Column::NftId.eq(token_id.nft_id as i64))
+                    .one(db)
+                    .await?;
+                let nft = Contract::get_nft(state, token_id).await?;
+                check_burn(token_id, nft.as_ref(), referenced_by.as_ref())?;
+                if let BurnNFTResult::Err(err) = Contract::burn_nft(state, token_id).await? {
+                    return Err(Error::new(format!("Contract error: {}", err)));
+                }
+            }
+        }
+        RepairAction::Dismiss => {}
+    }
+
+    let mut issue: reconciliation_issue::ActiveModel = issue.into();
+    issue.resolution = Set(Some(action.as_str().to_string()));
+    issue.resolved_at = Set(Some(Utc::now().naive_utc()));
+    Ok(issue.update(db).await?)
+}
+
+/// Mints a fresh token for the asset's stored content, to the wallet that
+/// held the previous one, and only then burns the mismatched token, so a
+/// failed mint leaves the asset on its old token
+async fn remint_asset(
+    db: &DatabaseConnection,
+    state: &AppState,
+    asset: asset::Model,
+    previous: Option<NFTDetails>,
+) -> Result<()> {
+    let backend = Contract::principal(state)?;
+    let owner = match &asset.owner_principal {
+        Some(owner) if *owner != backend.to_text() => Some(parse_principal(owner)?),
+        _ => None,
+    };
+    let result = Contract::mint_nft(
+        state,
+        asset.folder_id as u64,
+        &asset.uuid.to_string(),
+        &asset.ipfs_hash,
+        asset.plaintext_sha256.as_deref(),
+        owner,
+    )
+    .await?;
+    let res = match result {
+        MintNFTResult::Ok(res) => res,
+        MintNFTResult::Err(err) => return Err(Error::new(format!("Contract error: {}", err))),
+    };
+
+    let mut asset: asset::ActiveModel = asset.into();
+    asset.nft_id = Set(res.1.id as i64);
+    asset.mint_txn_id = Set(Some(res.0.to_string()));
+    asset.owner_principal = Set(Some(res.1.owner.to_text()));
+    asset.minted_at = Set(Utc::now().naive_utc());
+    asset.last_updated = Set(Utc::now().naive_utc());
+    asset.update(db).await?;
+
+    // The asset is consistent again, a token left behind shows up as
+    // orphaned on the next run
+    if let Some(previous) = previous {
+        let burned = Contract::burn_nft(state, previous.token_id()).await;
+        match burned {
+            Ok(BurnNFTResult::Ok(_)) => {}
+            Ok(BurnNFTResult::Err(err)) => tracing::warn!(
+                "Failed to burn token {} after reminting: {}",
+                previous.token_id(),
+                err
+            ),
+            Err(err) => tracing::warn!(
+                "Failed to burn token {} after reminting: {}",
+                previous.token_id(),
+                err.message
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::apps::assets::utils::contract::Asset;
+
+    fn backend() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn stored_asset() -> asset::Model {
+        let now = Utc::now().naive_utc();
+        asset::Model {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            name: "photo.jpg".to_string(),
+            description: String::new(),
+            size_mb: 1.0,
+            content_type: "image/jpeg".to_string(),
+            detected_content_type: None,
+            ipfs_hash: "QmStored".to_string(),
+            nft_id: 3,
+            client_id: 1,
+            folder_id: 7,
+            metadata: None,
+            encrypted_data_key: None,
+            encryption_key_id: None,
+            plaintext_sha256: None,
+            owner_principal: None,
+            mint_txn_id: None,
+            minted_at: now,
+            date_added: now,
+            last_updated: now,
+        }
+    }
+
+    fn token(asset: &asset::Model, ipfs_hash: &str, owner: Principal) -> NFTDetails {
+        let minted = Asset {
+            uuid: asset.uuid.to_string(),
+            ipfs_hash: ipfs_hash.to_string(),
+            plaintext_sha256: None,
+            date_added: asset.date_added.to_string(),
+        };
+        NFTDetails {
+            id: asset.nft_id as u64,
+            owner,
+            metadata: serde_json::to_string(&minted).unwrap(),
+            collection_id: asset.folder_id as u64,
+        }
+    }
+
+    #[test]
+    fn remints_only_assets_still_in_the_recorded_state() {
+        let asset = stored_asset();
+        let mismatched = token(&asset, "QmOther", backend());
+        let matching = token(&asset, &asset.ipfs_hash, backend());
+
+        assert!(check_remint(
+            ReconciliationIssueKind::MetadataMismatch,
+            &asset,
+            Some(&mismatched),
+            backend()
+        )
+        .is_ok());
+        assert!(check_remint(
+            ReconciliationIssueKind::MissingOnChain,
+            &asset,
+            None,
+            backend()
+        )
+        .is_ok());
+
+        // Fixed since the job ran
+        let err = check_remint(
+            ReconciliationIssueKind::MetadataMismatch,
+            &asset,
+            Some(&matching),
+            backend(),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("dismiss"));
+        // The token went missing after a mismatch was recorded
+        let err = check_remint(
+            ReconciliationIssueKind::MetadataMismatch,
+            &asset,
+            None,
+            backend(),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("new reconciliation"));
+        // A token that was given away cannot be burned after the remint
+        let held = token(&asset, "QmOther", Principal::from_slice(&[2]));
+        let err = check_remint(
+            ReconciliationIssueKind::MetadataMismatch,
+            &asset,
+            Some(&held),
+            backend(),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("must be returned"));
+    }
+
+    #[test]
+    fn burns_only_tokens_that_are_still_orphaned() {
+        let asset = stored_asset();
+        let nft = token(&asset, &asset.ipfs_hash, backend());
+        let token_id = nft.token_id();
+
+        assert!(check_burn(token_id, Some(&nft), None).is_ok());
+
+        // An asset was pointed at the token since the job ran
+        let err = check_burn(token_id, Some(&nft), Some(&asset)).unwrap_err();
+        assert!(err.message.contains("no longer orphaned"));
+        // Burned elsewhere in the meantime
+        let err = check_burn(token_id, None, None).unwrap_err();
+        assert!(err.message.contains("no longer exists"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use async_graphql::*;
+use entity::entities::thumbnail;
+use image::{DynamicImage, ImageFormat};
+use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use super::pinata::Pinata;
+use crate::config::state::AppState;
+
+/// Longest side, in pixels, of the thumbnails generated for every image
+pub const THUMBNAIL_SIZES: [u32; 3] = [128, 256, 512];
+
+pub struct GeneratedThumbnail {
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+pub enum ThumbnailOwner {
+    Asset(i32),
+    Folder(i32),
+}
+
+/// Resizes the image in `content` to each of `THUMBNAIL_SIZES`, skipping the
+/// sizes that would upscale it. The file is rewound so it can still be pinned.
+pub fn generate_thumbnails(content: &mut std::fs::File) -> Result<Vec<GeneratedThumbnail>> {
+    let mut bytes = Vec::new();
+    content.read_to_end(&mut bytes)?;
+    content.seek(SeekFrom::Start(0))?;
+
+    let image = image::load_from_memory(&bytes)?;
+    let longest_side = image.width().max(image.height());
+    let (format, content_type) = if image.color().has_alpha() {
+        (ImageFormat::Png, "image/png")
+    } else {
+        (ImageFormat::Jpeg, "image/jpeg")
+    };
+
+    let mut thumbnails = Vec::new();
+    for size in THUMBNAIL_SIZES {
+        if size >= longest_side {
+            continue;
+        }
+        let resized = image.thumbnail(size, size);
+        let resized = if format == ImageFormat::Jpeg {
+            DynamicImage::ImageRgb8(resized.to_rgb8())
+        } else {
+            resized
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        resized.write_to(&mut encoded, format)?;
+        thumbnails.push(GeneratedThumbnail {
+            size,
+            width: resized.width(),
+            height: resized.height(),
+            content_type,
+            bytes: encoded.into_inner(),
+        });
+    }
+    Ok(thumbnails)
+}
+
+/// Pins the thumbnails and links them to their owner. Thumbnails are a
+/// convenience, so failures are logged instead of failing the upload.
+pub async fn save_thumbnails(
+    db: &DatabaseConnection,
+    state: &AppState,
+    owner: ThumbnailOwner,
+    thumbnails: Vec<GeneratedThumbnail>,
+) -> Vec<thumbnail::Model> {
+    let (asset_id, folder_id) = match owner {
+        ThumbnailOwner::Asset(id) => (Some(id as i64), None),
+        ThumbnailOwner::Folder(id) => (None, Some(id as i64)),
+    };
+
+    let mut saved = Vec::with_capacity(thumbnails.len());
+    for item in thumbnails {
+        let pinned = match Pinata::pin_bytes(state, &item.bytes).await {
+            Ok(pinned) => pinned,
+            Err(err) => {
+                tracing::warn!("Failed to pin {}px thumbnail: {}", item.size, err.message);
+                continue;
+            }
+        };
+        let model = thumbnail::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            asset_id: Set(asset_id),
+            folder_id: Set(folder_id),
+            size: Set(item.size as i32),
+            width: Set(item.width as i32),
+            height: Set(item.height as i32),
+            content_type: Set(item.content_type.to_string()),
+            ipfs_hash: Set(pinned.ipfs_hash),
+            ..Default::default()
+        };
+        match model.insert(db).await {
+            Ok(model) => saved.push(model),
+            Err(err) => tracing::warn!("Failed to save {}px thumbnail: {}", item.size, err),
+        }
+    }
+    saved
+}
+
+/// Generates thumbnails when `content_type` is an image, logging failures
+pub fn image_thumbnails(
+    content_type: &str,
+    content: &mut std::fs::File,
+) -> Vec<GeneratedThumbnail> {
+    if !content_type.starts_with("image") {
+        return Vec::new();
+    }
+    match generate_thumbnails(content) {
+        Ok(thumbnails) => thumbnails,
+        Err(err) => {
+            tracing::warn!("Failed to generate thumbnails: {}", err.message);
+            Vec::new()
+        }
+    }
+}
+
+/// Links copies of `from`'s thumbnails to `to`, sharing the pinned files
+pub async fn copy_thumbnails(
+    db: &DatabaseConnection,
+    from: ThumbnailOwner,
+    to: ThumbnailOwner,
+) -> Result<()> {
+    let (asset_id, folder_id) = match to {
+        ThumbnailOwner::Asset(id) => (Some(id as i64), None),
+        ThumbnailOwner::Folder(id) => (None, Some(id as i64)),
+    };
+    let thumbnails = thumbnail::Entity::find()
+        .filter(owner_filter(from))
+        .all(db)
+        .await?;
+    for item in thumbnails {
+        thumbnail::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            asset_id: Set(asset_id),
+            folder_id: Set(folder_id),
+            size: Set(item.size),
+            width: Set(item.width),
+            height: Set(item.height),
+            content_type: Set(item.content_type),
+            ipfs_hash: Set(item.ipfs_hash),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    Ok(())
+}
+
+fn owner_filter(owner: ThumbnailOwner) -> sea_orm::Condition {
+    match owner {
+        ThumbnailOwner::Asset(id) => {
+            sea_orm::Condition::all().add(thumbnail::Column::AssetId.eq(id as i64))
+        }
+        ThumbnailOwner::Folder(id) => {
+            sea_orm::Condition::all().add(thumbnail::Column::FolderId.eq(id as i64))
+        }
+    }
+}
+
+/// Unpins and removes an owner's thumbnails, e.g. when its file is replaced
+pub async fn delete_thumbnails(
+    db: &DatabaseConnection,
+    state: &AppState,
+    owner: ThumbnailOwner,
+) -> Result<()> {
+    let thumbnails = thumbnail::Entity::find()
+        .filter(owner_filter(owner))
+        .all(db)
+        .await?;
+    for item in thumbnails {
+        let shared = thumbnail::Entity::find()
+            .filter(thumbnail::Column::IpfsHash.eq(item.ipfs_hash.clone()))
+            .filter(thumbnail::Column::Id.ne(item.id))
+            .one(db)
+            .await?;
+        if shared.is_none() {
+            Pinata::unpin_file(state, &item.ipfs_hash).await?;
+        }
+        item.delete(db).await?;
+    }
+    Ok(())
+}
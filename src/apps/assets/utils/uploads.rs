@@ -0,0 +1,320 @@
+use std::io::Read;
+
+use actix_web::web;
+use async_graphql::*;
+use candid::Principal;
+use entity::entities::{asset, client, folder, user};
+use sea_orm::{entity::*, DatabaseConnection, PaginatorTrait, QueryFilter};
+use uuid::Uuid;
+
+use super::{
+    contract::{parse_principal, Contract, CreateNFTResult, MintNFTResult},
+    encryption::encrypt_content,
+    files::sniff_content_type,
+    formating::format_id,
+    gps,
+    metadata::extract_metadata,
+    pinata::Pinata,
+    quota::UploadPolicy,
+    thumbnails::{
+        copy_thumbnails, image_thumbnails, save_thumbnails, GeneratedThumbnail, ThumbnailOwner,
+    },
+};
+use crate::config::{metrics::record_upload, state::AppState};
+
+/// Number of files from a batch that are pinned and minted at the same time
+pub const BATCH_UPLOAD_CONCURRENCY: usize = 4;
+pub const MAX_BATCH_UPLOAD_FILES: usize = 500;
+
+/// A file that has been read out of the request and is ready to be stored
+pub struct PendingAsset {
+    pub name: String,
+    pub description: String,
+    pub content_type: Option<String>,
+    pub size_mb: f64,
+    pub content: std::fs::File,
+    /// Leave GPS coordinates out of the extracted EXIF metadata
+    pub strip_gps: bool,
+    /// Wallet the NFT is minted to, the backend keeps it when `None`
+    pub owner: Option<Principal>,
+}
+
+/// The content of an upload with what is derived from it before it is stored
+pub struct PreparedContent {
+    pub content: std::fs::File,
+    pub thumbnails: Vec<GeneratedThumbnail>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Strips GPS coordinates when asked for, then generates the thumbnails and
+/// reads the metadata of the bytes that will be pinned. Decoding is CPU
+/// bound, so it leaves the worker's event loop.
+pub async fn prepare_content(
+    content: std::fs::File,
+    content_type: &str,
+    thumbnails: bool,
+    strip_gps: bool,
+) -> Result<PreparedContent> {
+    let content_type = content_type.to_string();
+    web::block(move || {
+        let mut content = content;
+        if strip_gps && content_type.starts_with("image") {
+            gps::strip_gps(&content_type, &mut content)?;
+        }
+        let thumbnails = if thumbnails {
+            image_thumbnails(&content_type, &mut content)
+        } else {
+            Vec::new()
+        };
+        let metadata = extract_metadata(&content_type, &mut content, strip_gps);
+        Ok(PreparedContent {
+            content,
+            thumbnails,
+            metadata,
+        })
+    })
+    .await
+    .map_err(|err| Error::new(err.to_string()))?
+}
+
+/// Who an upload's NFT is minted to: an explicit recipient first, then the
+/// uploader's linked wallet when asked for or set on the folder
+pub fn mint_owner(
+    user: &user::Model,
+    folder: &folder::Model,
+    recipient_principal: Option<&str>,
+    mint_to_wallet: Option<bool>,
+) -> Result<Option<Principal>> {
+    if let Some(recipient) = recipient_principal {
+        return Ok(Some(parse_principal(recipient)?));
+    }
+    if mint_to_wallet.unwrap_or(folder.mint_to_wallet) {
+        if let Some(wallet_address) = &user.wallet_address {
+            Ok(Some(parse_principal(wallet_address)?))
+        } else {
+            Err(Error::new(
+                "Link a wallet to your account before minting to it",
+            ))
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Pins the file, mints its NFT into the folder's collection and saves the
+/// asset. The folder's asset limit is left to the caller, which knows how
+/// many files it is about to add.
+pub async fn create_asset(
+    db: &DatabaseConnection,
+    state: &AppState,
+    folder: &folder::Model,
+    client_id: i32,
+    policy: &UploadPolicy,
+    pending: PendingAsset,
+) -> Result<asset::Model> {
+    policy.check_file_size(pending.size_mb)?;
+    let mut content = pending.content;
+    let sniffed = sniff_content_type(state, pending.content_type.as_deref(), &mut content)?;
+    policy.check_content_type(&sniffed.detected)?;
+    // Thumbnails are pinned in the clear, so encrypted folders go without
+    let PreparedContent {
+        content,
+        thumbnails,
+        metadata,
+    } = prepare_content(
+        content,
+        &sniffed.detected,
+        !folder.encrypted,
+        pending.strip_gps,
+    )
+    .await?;
+
+    let uuid = Uuid::new_v4();
+    let size_bytes = content.metadata()?.len();
+    let stored = store_content(db, state, folder, &uuid, content).await?;
+    let result = Contract::mint_nft(
+        state,
+        folder.id as u64,
+        &uuid.to_string(),
+        &stored.ipfs_hash,
+        stored.plaintext_sha256.as_deref(),
+        pending.owner,
+    )
+    .await?;
+
+    match result {
+        MintNFTResult::Ok(res) => {
+            let new_asset = asset::ActiveModel {
+                uuid: Set(uuid),
+                name: Set(pending.name),
+                description: Set(pending.description),
+                folder_id: Set(folder.id.into()),
+                nft_id: Set(res.1.id as i64),
+                mint_txn_id: Set(Some(res.0.to_string())),
+                owner_principal: Set(Some(res.1.owner.to_text())),
+                client_id: Set(client_id as i64),
+                ipfs_hash: Set(stored.ipfs_hash),
+                encrypted_data_key: Set(stored.encrypted_data_key),
+                encryption_key_id: Set(stored.encryption_key_id),
+                plaintext_sha256: Set(stored.plaintext_sha256),
+                size_mb: Set(pending.size_mb),
+                content_type: Set(sniffed.content_type),
+                detected_content_type: Set(Some(sniffed.detected)),
+                metadata: Set(metadata),
+                ..Default::default()
+            };
+            let new_asset = new_asset.insert(db).await?;
+            record_upload(&new_asset.content_type, size_bytes);
+            save_thumbnails(db, state, ThumbnailOwner::Asset(new_asset.id), thumbnails).await;
+            Ok(new_asset)
+        }
+        MintNFTResult::Err(err) => Err(Error::new(format!("Contract error: {}", err))),
+    }
+}
+
+/// Where an upload's content ended up in storage
+pub struct StoredContent {
+    pub ipfs_hash: String,
+    pub encrypted_data_key: Option<String>,
+    pub encryption_key_id: Option<String>,
+    pub plaintext_sha256: Option<String>,
+}
+
+/// Pins the content of an asset, encrypting it first when the folder is
+/// encrypted so only ciphertext reaches IPFS
+pub async fn store_content(
+    db: &DatabaseConnection,
+    state: &AppState,
+    folder: &folder::Model,
+    asset_uuid: &Uuid,
+    mut content: std::fs::File,
+) -> Result<StoredContent> {
+    if !folder.encrypted {
+        let pinned = Pinata::pin_file(state, content).await?;
+        return Ok(StoredContent {
+            ipfs_hash: pinned.ipfs_hash,
+            encrypted_data_key: None,
+            encryption_key_id: None,
+            plaintext_sha256: None,
+        });
+    }
+
+    let client = client::Entity::find_by_id(folder.client_id as i32)
+        .one(db)
+        .await?;
+    let client = match client {
+        Some(client) => client,
+        None => return Err(Error::new("Folder client was not found")),
+    };
+
+    let mut plaintext = Vec::new();
+    content.read_to_end(&mut plaintext)?;
+    let encrypted = encrypt_content(state, &client.uuid, asset_uuid, &plaintext).await?;
+    let pinned = Pinata::pin_bytes(state, &encrypted.ciphertext).await?;
+
+    Ok(StoredContent {
+        ipfs_hash: pinned.ipfs_hash,
+        encrypted_data_key: Some(encrypted.encrypted_data_key),
+        encryption_key_id: Some(encrypted.key_id),
+        plaintext_sha256: Some(encrypted.plaintext_sha256),
+    })
+}
+
+/// Settings a folder is created with, sub folders inherit them
+#[derive(Clone, Copy, Default)]
+pub struct FolderOptions {
+    pub encrypted: bool,
+    pub mint_to_wallet: bool,
+}
+
+impl From<&folder::Model> for FolderOptions {
+    fn from(value: &folder::Model) -> Self {
+        Self {
+            encrypted: value.encrypted,
+            mint_to_wallet: value.mint_to_wallet,
+        }
+    }
+}
+
+/// Creates the folder's NFT collection and saves the folder
+#[allow(clippy::too_many_arguments)]
+pub async fn create_folder(
+    db: &DatabaseConnection,
+    state: &AppState,
+    client_id: i32,
+    parent_id: Option<i64>,
+    name: String,
+    description: String,
+    logo_hash: String,
+    options: FolderOptions,
+) -> Result<folder::Model> {
+    let logo_url = Some(Pinata::build_url(state, logo_hash.clone()));
+    let count = folder::Entity::find().count(db).await?;
+    let symbol = format_id(count + 1);
+
+    let result = Contract::create_nft(state, &name, &symbol, &description, &logo_url).await?;
+
+    match result {
+        CreateNFTResult::Ok(res) => {
+            let folder = folder::ActiveModel {
+                id: Set(res.1.id as i32),
+                create_txn_id: Set(Some(res.0.to_string())),
+                uuid: Set(Uuid::new_v4()),
+                name: Set(name),
+                logo_hash: Set(logo_hash),
+                description: Set(description),
+                client_id: Set(client_id as i64),
+                parent_id: Set(parent_id),
+                encrypted: Set(options.encrypted),
+                mint_to_wallet: Set(options.mint_to_wallet),
+                ..Default::default()
+            };
+            let folder = folder.insert(db).await?;
+            Ok(folder)
+        }
+        CreateNFTResult::Err(err) => Err(Error::new(format!("Contract error: {}", err))),
+    }
+}
+
+/// Finds the sub folder called `name` inside `parent`, creating it with the
+/// parent's logo and options when it does not exist yet. The flag is `true`
+/// when the folder was created.
+pub async fn find_or_create_child_folder(
+    db: &DatabaseConnection,
+    state: &AppState,
+    policy: &UploadPolicy,
+    parent: &folder::Model,
+    name: &str,
+    description: &str,
+) -> Result<(folder::Model, bool)> {
+    let folder = folder::Entity::find()
+        .filter(folder::Column::ClientId.eq(parent.client_id))
+        .filter(folder::Column::ParentId.eq(parent.id as i64))
+        .filter(folder::Column::Name.eq(name))
+        .one(db)
+        .await?;
+
+    if let Some(folder) = folder {
+        Ok((folder, false))
+    } else {
+        policy.check_folders(db, parent.client_id, 1).await?;
+        let folder = create_folder(
+            db,
+            state,
+            parent.client_id as i32,
+            Some(parent.id as i64),
+            name.to_string(),
+            description.to_string(),
+            parent.logo_hash.clone(),
+            parent.into(),
+        )
+        .await?;
+        copy_thumbnails(
+            db,
+            ThumbnailOwner::Folder(parent.id),
+            ThumbnailOwner::Folder(folder.id),
+        )
+        .await?;
+        Ok((folder, true))
+    }
+}
@@ -1,16 +1,31 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use async_graphql::*;
 use entity::entities::{auth_token, profile, user};
 use sea_orm::{entity::*, sqlx::types::chrono, DatabaseConnection, QueryFilter, Set};
 use uuid::Uuid;
 
-use crate::apps::users::{
-    graphql::types::{
-        inputs::auth::{EmailPasswordSigninInput, EmailPasswordSignupInput},
-        outputs::users::{AuthTokenType, UserType},
+use crate::{
+    apps::users::{
+        graphql::types::{
+            inputs::auth::{
+                EmailPasswordSigninInput, EmailPasswordSignupInput, WalletSignatureInput,
+            },
+            outputs::users::{AuthTokenType, UserType, WalletChallengeType},
+        },
+        utils::{
+            auth::create_user_auth_token,
+            security::{
+                check_password_signin, notify_lockout, record_failed_signin, record_security_event,
+                record_successful_signin, PasswordSignin, SecurityEventKind, INVALID_CREDENTIALS,
+            },
+            wallet::{create_wallet_challenge, verify_wallet_signature},
+        },
+    },
+    config::{
+        rate_limit::{ClientIp, RateLimiter},
+        state::AppState,
     },
-    utils::auth::create_user_auth_token,
 };
 
 #[derive(Default)]
@@ -24,12 +39,16 @@ impl UsersAuthMutations {
         input: EmailPasswordSignupInput,
     ) -> Result<UserType> {
         let db = ctx.data::<DatabaseConnection>()?;
+        let rate_limiter = ctx.data::<Arc<RateLimiter>>()?;
+        rate_limiter.check_ip(ctx).await?;
+        rate_limiter.check_email(ctx, &input.email).await?;
+
         if input.password1 != input.password2 {
             return Err(Error::new("Passwords do not match"));
         }
         let password_hash = bcrypt::hash(input.password1, bcrypt::DEFAULT_COST)?;
         let new_user = user::ActiveModel {
-            email: Set(input.email),
+            email: Set(Some(input.email)),
             uuid: Set(Uuid::new_v4()),
             password_hash: Set(Some(password_hash)),
             ..Default::default()
@@ -52,33 +71,129 @@ impl UsersAuthMutations {
         input: EmailPasswordSigninInput,
     ) -> Result<AuthTokenType> {
         let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let rate_limiter = ctx.data::<Arc<RateLimiter>>()?;
+        rate_limiter.check_ip(ctx).await?;
+        rate_limiter.check_email(ctx, &input.email).await?;
+
+        let ip = ctx.data_opt::<ClientIp>().and_then(|ip| ip.0);
 
         let user = user::Entity::find()
             .filter(user::Column::Email.eq(input.email))
             .one(db)
             .await?;
 
-        if let Some(user) = user {
-            if let Some(password_hash) = &user.password_hash {
-                if bcrypt::verify(input.password, password_hash.as_str())? {
-                    let tokens = auth_token::Entity::find()
-                        .filter(auth_token::Column::UserId.eq(user.id as i64))
-                        .all(db)
-                        .await?;
-                    for token in tokens {
-                        token.delete(db).await?;
-                    }
-                    let new_token = create_user_auth_token(&user, db).await?;
-                    return Ok(new_token.into());
-                } else {
-                    return Err(Error::new("Incorrect email or password"));
+        let outcome = check_password_signin(
+            user.as_ref(),
+            &input.password,
+            chrono::Utc::now().naive_utc(),
+        )?;
+        let user_id = user.as_ref().map(|user| user.id);
+        match (outcome, &user) {
+            (PasswordSignin::WrongPassword, Some(user)) => {
+                if let Some(locked_until) = record_failed_signin(db, state, user, ip).await? {
+                    notify_lockout(state.clone(), user, locked_until);
                 }
-            } else {
-                return Err(Error::new("Password login not found"));
             }
-        } else {
-            return Err(Error::new("User with that email not found"));
+            (PasswordSignin::Throttled, _) => {
+                record_security_event(db, user_id, SecurityEventKind::SigninThrottled, ip, None)
+                    .await?
+            }
+            (PasswordSignin::NoPassword, _) => {
+                record_security_event(
+                    db,
+                    user_id,
+                    SecurityEventKind::SigninFailed,
+                    ip,
+                    Some(String::from("No password set")),
+                )
+                .await?
+            }
+            (PasswordSignin::UnknownEmail, _) => {
+                record_security_event(
+                    db,
+                    None,
+                    SecurityEventKind::SigninFailed,
+                    ip,
+                    Some(String::from("Unknown email")),
+                )
+                .await?
+            }
+            _ => {}
         }
+        outcome.into_result()?;
+        let user = match user {
+            Some(user) => user,
+            None => return Err(Error::new(INVALID_CREDENTIALS)),
+        };
+
+        record_successful_signin(db, &user, ip).await?;
+        let tokens = auth_token::Entity::find()
+            .filter(auth_token::Column::UserId.eq(user.id as i64))
+            .all(db)
+            .await?;
+        for token in tokens {
+            token.delete(db).await?;
+        }
+        let new_token = create_user_auth_token(&user, db, state).await?;
+        Ok(new_token.into())
+    }
+
+    /// Issues a nonce to sign with an ICP identity for `walletSignin`
+    async fn create_wallet_challenge<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<WalletChallengeType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        ctx.data::<Arc<RateLimiter>>()?.check_ip(ctx).await?;
+        let challenge = create_wallet_challenge(db).await?;
+        Ok(challenge.into())
+    }
+
+    /// Signs in as the principal that signed the challenge, creating its user
+    /// on first sign in
+    async fn wallet_signin<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: WalletSignatureInput,
+    ) -> Result<AuthTokenType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        ctx.data::<Arc<RateLimiter>>()?.check_ip(ctx).await?;
+        let principal = verify_wallet_signature(db, state, &input).await?;
+
+        let user = user::Entity::find()
+            .filter(user::Column::WalletAddress.eq(principal.to_text()))
+            .one(db)
+            .await?;
+        let user = if let Some(user) = user {
+            let tokens = auth_token::Entity::find()
+                .filter(auth_token::Column::UserId.eq(user.id as i64))
+                .all(db)
+                .await?;
+            for token in tokens {
+                token.delete(db).await?;
+            }
+            user
+        } else {
+            let new_user = user::ActiveModel {
+                uuid: Set(Uuid::new_v4()),
+                wallet_address: Set(Some(principal.to_text())),
+                ..Default::default()
+            };
+            let new_user: user::Model = new_user.insert(db).await?;
+
+            let profile = profile::ActiveModel {
+                uuid: Set(Uuid::new_v4()),
+                user_id: Set(new_user.id as i64),
+                ..Default::default()
+            };
+            profile.insert(db).await?;
+            new_user
+        };
+
+        let new_token = create_user_auth_token(&user, db, state).await?;
+        Ok(new_token.into())
     }
 
     async fn refresh_token<'ctx>(
@@ -87,6 +202,8 @@ impl UsersAuthMutations {
         refresh_token: String,
     ) -> Result<AuthTokenType> {
         let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        ctx.data::<Arc<RateLimiter>>()?.check_ip(ctx).await?;
 
         let auth_token = auth_token::Entity::find()
             .filter(auth_token::Column::Uuid.eq(Uuid::from_str(refresh_token.as_str())?))
@@ -104,7 +221,7 @@ impl UsersAuthMutations {
                 if let Some(user) = user {
                     token.delete(db).await?;
 
-                    let new_token = create_user_auth_token(&user, db).await?;
+                    let new_token = create_user_auth_token(&user, db, state).await?;
                     Ok(new_token.into())
                 } else {
                     return Err(Error::new("Invalid refresh_token"));
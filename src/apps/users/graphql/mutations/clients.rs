@@ -2,15 +2,19 @@ use async_graphql::*;
 use chrono::{Datelike, Utc};
 use entity::entities::{client, client_package_subscription, subscription_package, user};
 use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use serde_json::json;
 use std::{
     hash::{DefaultHasher, Hash, Hasher},
     str::FromStr,
 };
 use uuid::Uuid;
 
-use crate::apps::users::graphql::types::{
-    inputs::clients::{ClientPackageSubscriptionInput, SubscriptionPackageInput},
-    outputs::clients::{ClientPackageSubscriptionType, SubscriptionPackageType},
+use crate::{
+    apps::users::graphql::types::{
+        inputs::clients::{ClientPackageSubscriptionInput, SubscriptionPackageInput},
+        outputs::clients::{ClientPackageSubscriptionType, SubscriptionPackageType},
+    },
+    config::telemetry::record_client,
 };
 
 #[derive(Default)]
@@ -58,6 +62,7 @@ impl UserClientMutations {
                     };
                     client.insert(db).await?
                 };
+                record_client(client.id);
                 let today = Utc::now().naive_utc();
                 let one_month_ahead = today
                     .with_month(today.month() + 1)
@@ -110,6 +115,13 @@ impl UserClientMutations {
                 package.storage_capacity_mb = Set(input.storage_capacity_mb);
                 package.monthly_requests = Set(input.monthly_requests);
                 package.max_allowed_sessions = Set(input.max_allowed_sessions);
+                package.max_file_size_mb = Set(input.max_file_size_mb);
+                package.allowed_content_types =
+                    Set(input.allowed_content_types.map(|types| json!(types)));
+                package.denied_content_types =
+                    Set(input.denied_content_types.map(|types| json!(types)));
+                package.max_assets_per_folder = Set(input.max_assets_per_folder);
+                package.max_folders = Set(input.max_folders);
                 package.last_updated = Set(Utc::now().naive_utc());
 
                 let package: subscription_package::Model = package.update(db).await?;
@@ -128,6 +140,11 @@ impl UserClientMutations {
                 storage_capacity_mb: Set(input.storage_capacity_mb),
                 monthly_requests: Set(input.monthly_requests),
                 max_allowed_sessions: Set(input.max_allowed_sessions),
+                max_file_size_mb: Set(input.max_file_size_mb),
+                allowed_content_types: Set(input.allowed_content_types.map(|types| json!(types))),
+                denied_content_types: Set(input.denied_content_types.map(|types| json!(types))),
+                max_assets_per_folder: Set(input.max_assets_per_folder),
+                max_folders: Set(input.max_folders),
                 ..Default::default()
             };
             let package: subscription_package::Model = package.insert(db).await?;
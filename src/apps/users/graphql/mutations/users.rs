@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use async_graphql::*;
+use chrono::Utc;
+use entity::entities::user;
+use sea_orm::{entity::*, DatabaseConnection, QueryFilter};
+
+use crate::{
+    apps::users::{
+        graphql::types::{inputs::auth::WalletSignatureInput, outputs::users::UserType},
+        utils::wallet::verify_wallet_signature,
+    },
+    config::state::AppState,
+};
+
+#[derive(Default)]
+pub struct UserMutations;
+
+#[Object]
+impl UserMutations {
+    /// Links the principal that signed the challenge, assets can then be
+    /// minted to it and it can be used to sign in
+    async fn link_wallet<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: WalletSignatureInput,
+    ) -> Result<UserType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if let Some(user) = user {
+            let principal = verify_wallet_signature(db, state, &input).await?.to_text();
+            let linked = user::Entity::find()
+                .filter(user::Column::WalletAddress.eq(principal.as_str()))
+                .one(db)
+                .await?;
+            if linked.is_some_and(|linked| linked.id != user.id) {
+                return Err(Error::new("This wallet is linked to another account"));
+            }
+
+            let mut user: user::ActiveModel = user.clone().into();
+            user.wallet_address = Set(Some(principal));
+            user.last_updated = Set(Utc::now().naive_utc());
+            let user = user.update(db).await?;
+            Ok(user.into())
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+
+    async fn unlink_wallet<'ctx>(&self, ctx: &Context<'ctx>) -> Result<UserType> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+
+        if let Some(user) = user {
+            if user.email.is_none() {
+                return Err(Error::new(
+                    "The wallet is the only way to sign in to this account",
+                ));
+            }
+
+            let mut user: user::ActiveModel = user.clone().into();
+            user.wallet_address = Set(None);
+            user.last_updated = Set(Utc::now().naive_utc());
+            let user = user.update(db).await?;
+            Ok(user.into())
+        } else {
+            Err(Error::new(
+                "You must be authenticated to perform this action",
+            ))
+        }
+    }
+}
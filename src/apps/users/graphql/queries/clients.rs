@@ -2,7 +2,10 @@ use async_graphql::*;
 use entity::entities::{client, subscription_package, user};
 use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
 
-use crate::apps::users::graphql::types::outputs::clients::{ClientType, SubscriptionPackageType};
+use crate::{
+    apps::users::graphql::types::outputs::clients::{ClientType, SubscriptionPackageType},
+    config::telemetry::record_client,
+};
 
 #[derive(Default)]
 pub struct UserClientQueries;
@@ -29,7 +32,10 @@ impl UserClientQueries {
                 .one(db)
                 .await?
             {
-                Some(client) => Ok(Some(client.into())),
+                Some(client) => {
+                    record_client(client.id);
+                    Ok(Some(client.into()))
+                }
                 None => Ok(None),
             }
         } else {
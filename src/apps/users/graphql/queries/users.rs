@@ -1,6 +1,10 @@
-use crate::apps::users::graphql::types::outputs::users::UserType;
+use crate::apps::users::graphql::types::outputs::users::{SecurityEventType, UserType};
 use async_graphql::*;
-use entity::entities::user;
+use entity::entities::{security_event, user};
+use sea_orm::{entity::*, DatabaseConnection, QueryFilter, QueryOrder, QuerySelect};
+
+/// Most events `securityEvents` returns
+const MAX_SECURITY_EVENTS: u64 = 100;
 
 #[derive(Default)]
 pub struct UserQueries;
@@ -15,4 +19,25 @@ impl UserQueries {
             Ok(None)
         }
     }
+
+    /// The latest sign ins and lockouts of the signed in account
+    async fn security_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        limit: Option<u64>,
+    ) -> Result<Vec<SecurityEventType>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = ctx.data::<Option<user::Model>>()?;
+        if let Some(user) = user {
+            let events = security_event::Entity::find()
+                .filter(security_event::Column::UserId.eq(user.id as i64))
+                .order_by_desc(security_event::Column::Id)
+                .limit(limit.unwrap_or(20).min(MAX_SECURITY_EVENTS))
+                .all(db)
+                .await?;
+            Ok(events.into_iter().map(|event| event.into()).collect())
+        } else {
+            Err(Error::new("You are not authorized to perform this action"))
+        }
+    }
 }
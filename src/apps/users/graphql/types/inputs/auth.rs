@@ -12,3 +12,26 @@ pub struct EmailPasswordSigninInput {
     pub email: String,
     pub password: String,
 }
+
+/// A delegation as serialized by agent-js's `DelegationChain.toJSON()`
+#[derive(InputObject)]
+pub struct SignedDelegationInput {
+    /// Hex DER public key the delegation is given to
+    pub pubkey: String,
+    /// Hex nanosecond timestamp after which the delegation is invalid
+    pub expiration: String,
+    /// Hex encoded canister principals the delegation is restricted to
+    pub targets: Option<Vec<String>>,
+    pub signature: String,
+}
+
+/// A wallet challenge signed with an ICP identity. `publicKey` is the hex DER
+/// key of the identity, when `delegations` are given the challenge is signed
+/// by the key at the end of the chain.
+#[derive(InputObject)]
+pub struct WalletSignatureInput {
+    pub nonce: String,
+    pub public_key: String,
+    pub signature: String,
+    pub delegations: Option<Vec<SignedDelegationInput>>,
+}
@@ -8,6 +8,12 @@ pub struct SubscriptionPackageInput {
     pub storage_capacity_mb: f64,
     pub monthly_requests: i64,
     pub max_allowed_sessions: i32,
+    pub max_file_size_mb: Option<f64>,
+    /// Content types or categories (e.g. `image`, `video/mp4`) that may be uploaded
+    pub allowed_content_types: Option<Vec<String>>,
+    pub denied_content_types: Option<Vec<String>>,
+    pub max_assets_per_folder: Option<i32>,
+    pub max_folders: Option<i32>,
 }
 
 #[derive(InputObject)]
@@ -115,6 +115,11 @@ pub struct SubscriptionPackageType {
     pub storage_capacity_mb: f64,
     pub monthly_requests: i64,
     pub max_allowed_sessions: i32,
+    pub max_file_size_mb: Option<f64>,
+    pub allowed_content_types: Option<Vec<String>>,
+    pub denied_content_types: Option<Vec<String>>,
+    pub max_assets_per_folder: Option<i32>,
+    pub max_folders: Option<i32>,
     pub date_added: String,
     pub last_updated: String,
 }
@@ -129,6 +134,15 @@ impl From<subscription_package::Model> for SubscriptionPackageType {
             storage_capacity_mb: value.storage_capacity_mb,
             monthly_requests: value.monthly_requests,
             max_allowed_sessions: value.max_allowed_sessions,
+            max_file_size_mb: value.max_file_size_mb,
+            allowed_content_types: value
+                .allowed_content_types
+                .and_then(|types| serde_json::from_value(types).ok()),
+            denied_content_types: value
+                .denied_content_types
+                .and_then(|types| serde_json::from_value(types).ok()),
+            max_assets_per_folder: value.max_assets_per_folder,
+            max_folders: value.max_folders,
             date_added: value.date_added.to_string(),
             last_updated: value.last_updated.to_string(),
         }
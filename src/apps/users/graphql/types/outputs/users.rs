@@ -1,5 +1,5 @@
 use async_graphql::*;
-use entity::entities::{auth_token, client, profile, user};
+use entity::entities::{auth_token, client, profile, security_event, user, wallet_challenge};
 use sea_orm::{entity::*, DatabaseConnection, EntityTrait, QueryFilter};
 
 use super::clients::ClientType;
@@ -9,8 +9,9 @@ use super::clients::ClientType;
 pub struct UserType {
     pub id: ID,
     pub uuid: String,
-    pub email: String,
+    pub email: Option<String>,
     pub wallet_address: Option<String>,
+    pub is_admin: bool,
     pub date_added: String,
     pub last_updated: String,
 }
@@ -22,6 +23,7 @@ impl From<user::Model> for UserType {
             uuid: value.uuid.into(),
             email: value.email,
             wallet_address: value.wallet_address,
+            is_admin: value.is_admin,
             date_added: value.date_added.to_string(),
             last_updated: value.last_updated.to_string(),
         }
@@ -132,3 +134,43 @@ impl From<auth_token::Model> for AuthTokenType {
         }
     }
 }
+
+/// A nonce to sign with a wallet, the signed bytes are `message` as UTF-8
+#[derive(SimpleObject)]
+pub struct WalletChallengeType {
+    pub nonce: String,
+    pub message: String,
+    pub expires_at: String,
+}
+
+impl From<wallet_challenge::Model> for WalletChallengeType {
+    fn from(value: wallet_challenge::Model) -> Self {
+        Self {
+            nonce: value.uuid.to_string(),
+            message: value.message,
+            expires_at: value.expires_at.to_string(),
+        }
+    }
+}
+
+/// A sign in or lockout of the account, from the security log
+#[derive(SimpleObject)]
+pub struct SecurityEventType {
+    pub uuid: String,
+    pub kind: String,
+    pub ip_address: Option<String>,
+    pub detail: Option<String>,
+    pub date_added: String,
+}
+
+impl From<security_event::Model> for SecurityEventType {
+    fn from(value: security_event::Model) -> Self {
+        Self {
+            uuid: value.uuid.into(),
+            kind: value.kind,
+            ip_address: value.ip_address,
+            detail: value.detail,
+            date_added: value.date_added.to_string(),
+        }
+    }
+}
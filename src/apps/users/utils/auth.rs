@@ -1,16 +1,19 @@
+use actix_web::http::header::HeaderMap;
 use async_graphql::*;
 use entity::entities::{auth_token, user};
-use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::Validation;
 use sea_orm::{entity::*, DatabaseConnection, QueryFilter, Set};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::config::settings::ENV;
+use crate::config::{state::AppState, telemetry::record_user};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum JWTVariant {
     User(String),
     Client(i32),
+    /// Users that signed in with a wallet and have no email
+    Wallet(String),
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -25,11 +28,17 @@ pub struct CustomJWTClaims {
 pub async fn create_user_auth_token(
     user: &user::Model,
     db: &DatabaseConnection,
+    state: &AppState,
 ) -> Result<auth_token::Model> {
     let expires_at = chrono::Utc::now() + std::time::Duration::from_secs_f32(24.0 * 60.0 * 60.0);
 
+    let varaint = match (&user.email, &user.wallet_address) {
+        (Some(email), _) => JWTVariant::User(email.clone()),
+        (None, Some(wallet_address)) => JWTVariant::Wallet(wallet_address.clone()),
+        (None, None) => return Err(Error::new("User has no email or wallet to sign in with")),
+    };
     let custom_claims = CustomJWTClaims {
-        varaint: JWTVariant::User(user.email.clone()),
+        varaint,
         iat: chrono::Utc::now().timestamp() as usize,
         exp: expires_at.clone().timestamp() as usize,
         iss: String::from("veecerts"),
@@ -38,7 +47,7 @@ pub async fn create_user_auth_token(
     let token = jsonwebtoken::encode(
         &jsonwebtoken::Header::default(),
         &custom_claims,
-        &EncodingKey::from_secret(ENV::init().secret_key.as_ref()),
+        &state.jwt_encoding_key,
     )?;
 
     let new_token = auth_token::ActiveModel {
@@ -55,21 +64,67 @@ pub async fn create_user_auth_token(
 pub async fn decode_user_auth_token(
     authorization: String,
     db: &DatabaseConnection,
+    state: &AppState,
 ) -> Result<Option<user::Model>> {
     if let Some(parts) = authorization.split_once(" ") {
         let token = jsonwebtoken::decode::<CustomJWTClaims>(
             parts.1,
-            &DecodingKey::from_secret(ENV::init().secret_key.as_ref()),
+            &state.jwt_decoding_key,
             &Validation::default(),
         )?;
-        if let JWTVariant::User(email) = token.claims.varaint {
-            let user = user::Entity::find()
-                .filter(user::Column::Email.eq(email))
-                .one(db)
-                .await?;
-            Ok(user)
-        } else {
-            Ok(None)
+        match token.claims.varaint {
+            JWTVariant::User(email) => {
+                let user = user::Entity::find()
+                    .filter(user::Column::Email.eq(email))
+                    .one(db)
+                    .await?;
+                Ok(user)
+            }
+            JWTVariant::Wallet(wallet_address) => {
+                let user = user::Entity::find()
+                    .filter(user::Column::WalletAddress.eq(wallet_address))
+                    .one(db)
+                    .await?;
+                Ok(user)
+            }
+            JWTVariant::Client(_) => Ok(None),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// The signed in user when they are an admin
+pub fn admin_user(user: &Option<user::Model>) -> Result<&user::Model> {
+    match user {
+        Some(user) if user.is_admin => Ok(user),
+        Some(_) => Err(Error::new("You are not authorized to perform this action")),
+        None => Err(Error::new(
+            "You must be authenticated to perform this action",
+        )),
+    }
+}
+
+pub async fn get_user_from_header(
+    headers: &HeaderMap,
+    db: &DatabaseConnection,
+    state: &AppState,
+) -> Result<Option<user::Model>> {
+    let token_str = headers
+        .get("Authorization")
+        .map(|value| Some(value.as_ref()));
+
+    if let Some(token_str) = token_str {
+        match token_str {
+            Some(token) => {
+                let user =
+                    decode_user_auth_token(String::from_utf8(token.to_vec())?, db, state).await?;
+                if let Some(user) = &user {
+                    record_user(user);
+                }
+                Ok(user)
+            }
+            _ => Ok(None),
         }
     } else {
         Ok(None)
@@ -1 +1,3 @@
 pub mod auth;
+pub mod security;
+pub mod wallet;
@@ -0,0 +1,465 @@
+use std::{
+    net::IpAddr,
+    sync::{Arc, OnceLock},
+};
+
+use async_graphql::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use entity::entities::{security_event, user};
+use sea_orm::{
+    entity::*, sea_query::Expr, DatabaseConnection, QueryFilter, QuerySelect, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::config::state::AppState;
+
+/// The only error of a refused password sign in, so it does not tell
+/// whether the account exists, is locked or got a wrong password
+pub const INVALID_CREDENTIALS: &str = "Incorrect email or password";
+
+/// Longest wait between failed sign ins before the account locks
+const MAX_SIGNIN_DELAY_SECS: i64 = 60;
+
+#[derive(Clone, Copy)]
+pub enum SecurityEventKind {
+    SigninSucceeded,
+    SigninFailed,
+    /// A sign in during a lockout or before the delay after a failure ended
+    SigninThrottled,
+    AccountLocked,
+}
+
+impl SecurityEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityEventKind::SigninSucceeded => "signin_succeeded",
+            SecurityEventKind::SigninFailed => "signin_failed",
+            SecurityEventKind::SigninThrottled => "signin_throttled",
+            SecurityEventKind::AccountLocked => "account_locked",
+        }
+    }
+}
+
+/// Adds an event to the security log, also written to the `security`
+/// tracing target
+pub async fn record_security_event(
+    db: &DatabaseConnection,
+    user_id: Option<i32>,
+    kind: SecurityEventKind,
+    ip: Option<IpAddr>,
+    detail: Option<String>,
+) -> Result<()> {
+    let ip = ip.map(|ip| ip.to_string());
+    tracing::info!(
+        target: "security",
+        kind = kind.as_str(),
+        user_id,
+        ip = ip.as_deref(),
+        detail = detail.as_deref(),
+        "security event"
+    );
+
+    security_event::ActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        user_id: Set(user_id.map(i64::from)),
+        kind: Set(kind.as_str().to_string()),
+        ip_address: Set(ip),
+        detail: Set(detail),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+/// Spends the time of a password check on sign ins refused without one, so
+/// unknown and locked accounts answer as slowly as the others
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| {
+        bcrypt::hash(Uuid::new_v4().to_string(), bcrypt::DEFAULT_COST).unwrap_or_default()
+    });
+    let _ = bcrypt::verify(password, hash);
+}
+
+/// How a password sign in went, decided before anything is recorded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PasswordSignin {
+    Accepted,
+    WrongPassword,
+    /// During a lockout or before the delay after a failure ended
+    Throttled,
+    /// The account only signs in with a wallet
+    NoPassword,
+    UnknownEmail,
+}
+
+impl PasswordSignin {
+    /// Every refusal fails with `INVALID_CREDENTIALS`
+    pub fn into_result(self) -> Result<()> {
+        match self {
+            PasswordSignin::Accepted => Ok(()),
+            _ => Err(Error::new(INVALID_CREDENTIALS)),
+        }
+    }
+}
+
+/// Checks `password` against the account of the email, if there is one,
+/// spending the time of a password check whatever the outcome
+pub fn check_password_signin(
+    user: Option<&user::Model>,
+    password: &str,
+    now: NaiveDateTime,
+) -> Result<PasswordSignin> {
+    let (user, password_hash) = match user {
+        Some(user) => match &user.password_hash {
+            Some(password_hash) => (user, password_hash),
+            None => {
+                verify_dummy_password(password);
+                return Ok(PasswordSignin::NoPassword);
+            }
+        },
+        None => {
+            verify_dummy_password(password);
+            return Ok(PasswordSignin::UnknownEmail);
+        }
+    };
+    if signin_blocked_until(user, now).is_some() {
+        verify_dummy_password(password);
+        return Ok(PasswordSignin::Throttled);
+    }
+    if bcrypt::verify(password, password_hash)? {
+        Ok(PasswordSignin::Accepted)
+    } else {
+        Ok(PasswordSignin::WrongPassword)
+    }
+}
+
+/// When the account may try signing in again: the end of its lockout, or
+/// of the delay that doubles with every failed sign in
+pub fn signin_blocked_until(user: &user::Model, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    if let Some(locked_until) = user.locked_until {
+        if locked_until > now {
+            return Some(locked_until);
+        }
+    }
+    if let Some(last_failed) = user.last_failed_signin_at {
+        if user.failed_signin_attempts > 0 {
+            let delay_secs = 2_i64
+                .pow((user.failed_signin_attempts - 1).min(6) as u32)
+                .min(MAX_SIGNIN_DELAY_SECS);
+            let next_attempt = last_failed + Duration::seconds(delay_secs);
+            if next_attempt > now {
+                return Some(next_attempt);
+            }
+        }
+    }
+    None
+}
+
+/// What a failed sign in leaves on the account
+#[derive(Debug, PartialEq)]
+struct FailedSignin {
+    attempts: i32,
+    locked_until: Option<NaiveDateTime>,
+}
+
+/// Adds a failure to the ones of the last lockout period, locking the
+/// account and starting the count over once `threshold` follow each other
+fn count_failed_signin(
+    user: &user::Model,
+    now: NaiveDateTime,
+    lockout: Duration,
+    threshold: i32,
+) -> FailedSignin {
+    // Failures from before the last lockout period no longer count
+    let previous = match user.last_failed_signin_at {
+        Some(last_failed) if last_failed >= now - lockout => user.failed_signin_attempts,
+        _ => 0,
+    };
+    let attempts = previous + 1;
+    if threshold > 0 && attempts >= threshold {
+        FailedSignin {
+            attempts: 0,
+            locked_until: Some(now + lockout),
+        }
+    } else {
+        FailedSignin {
+            attempts,
+            locked_until: None,
+        }
+    }
+}
+
+/// Counts a wrong password, locking the account once
+/// `SIGNIN_LOCKOUT_THRESHOLD` failures follow each other. Returns when the
+/// lock ends if this failure locked it.
+pub async fn record_failed_signin(
+    db: &DatabaseConnection,
+    state: &AppState,
+    user: &user::Model,
+    ip: Option<IpAddr>,
+) -> Result<Option<NaiveDateTime>> {
+    let env = &state.env;
+    let now = Utc::now().naive_utc();
+    let lockout = Duration::minutes(env.signin_lockout_minutes);
+
+    // Under a row lock concurrent failures are counted one after the other,
+    // so they lock the account only once
+    let txn = db.begin().await?;
+    let current = user::Entity::find_by_id(user.id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    let current = match current {
+        Some(current) => current,
+        None => return Ok(None),
+    };
+    let failed = count_failed_signin(&current, now, lockout, env.signin_lockout_threshold);
+    let mut current: user::ActiveModel = current.into();
+    current.failed_signin_attempts = Set(failed.attempts);
+    current.last_failed_signin_at = Set(Some(now));
+    if failed.locked_until.is_some() {
+        current.locked_until = Set(failed.locked_until);
+    }
+    current.update(&txn).await?;
+    txn.commit().await?;
+    record_security_event(db, Some(user.id), SecurityEventKind::SigninFailed, ip, None).await?;
+
+    if let Some(locked_until) = failed.locked_until {
+        record_security_event(
+            db,
+            Some(user.id),
+            SecurityEventKind::AccountLocked,
+            ip,
+            Some(format!(
+                "Locked until {} after {} failed sign ins",
+                locked_until.format("%Y-%m-%d %H:%M:%S"),
+                env.signin_lockout_threshold
+            )),
+        )
+        .await?;
+    }
+    Ok(failed.locked_until)
+}
+
+/// Clears the failures of an account that signed in
+pub async fn record_successful_signin(
+    db: &DatabaseConnection,
+    user: &user::Model,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    if user.failed_signin_attempts > 0 || user.locked_until.is_some() {
+        user::Entity::update_many()
+            .col_expr(user::Column::FailedSigninAttempts, Expr::value(0))
+            .col_expr(
+                user::Column::LastFailedSigninAt,
+                Expr::value(Option::<NaiveDateTime>::None),
+            )
+            .col_expr(
+                user::Column::LockedUntil,
+                Expr::value(Option::<NaiveDateTime>::None),
+            )
+            .filter(user::Column::Id.eq(user.id))
+            .exec(db)
+            .await?;
+    }
+    record_security_event(
+        db,
+        Some(user.id),
+        SecurityEventKind::SigninSucceeded,
+        ip,
+        None,
+    )
+    .await
+}
+
+/// Emails the owner of a locked account in the background, so the response
+/// does not wait on the SMTP server
+pub fn notify_lockout(state: Arc<AppState>, user: &user::Model, locked_until: NaiveDateTime) {
+    let email = match &user.email {
+        Some(email) => email.clone(),
+        None => return,
+    };
+    if state.mailer.is_none() {
+        tracing::warn!(
+            target: "security",
+            user_id = user.id,
+            "SMTP is not configured, the account lockout was not emailed"
+        );
+        return;
+    }
+
+    let user_id = user.id;
+    actix_web::rt::spawn(async move {
+        if let Some(mailer) = &state.mailer {
+            let body = format!(
+                "Your Veecerts account was locked after several failed sign in attempts.\n\n\
+                 You can sign in again after {} UTC. If these attempts were not yours, \
+                 someone may be trying to guess your password.",
+                locked_until.format("%Y-%m-%d %H:%M")
+            );
+            if let Err(err) = mailer
+                .send(&email, "Your Veecerts account was locked", body)
+                .await
+            {
+                tracing::warn!(
+                    target: "security",
+                    user_id,
+                    error = %err.message,
+                    "Account lockout email could not be sent"
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> NaiveDateTime {
+        chrono::DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc()
+    }
+
+    fn account(password: Option<&str>) -> user::Model {
+        user::Model {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            email: Some(String::from("user@example.com")),
+            wallet_address: None,
+            password_hash: password.map(|password| bcrypt::hash(password, 4).unwrap()),
+            is_admin: false,
+            failed_signin_attempts: 0,
+            last_failed_signin_at: None,
+            locked_until: None,
+            date_added: now(),
+            last_updated: now(),
+        }
+    }
+
+    fn failed(attempts: i32, minutes_ago: i64) -> user::Model {
+        user::Model {
+            failed_signin_attempts: attempts,
+            last_failed_signin_at: Some(now() - Duration::minutes(minutes_ago)),
+            ..account(None)
+        }
+    }
+
+    #[test]
+    fn counts_failures_of_the_lockout_period() {
+        let lockout = Duration::minutes(15);
+        let first = count_failed_signin(&account(None), now(), lockout, 5);
+        assert_eq!(first.attempts, 1);
+        assert_eq!(first.locked_until, None);
+
+        assert_eq!(
+            count_failed_signin(&failed(2, 10), now(), lockout, 5).attempts,
+            3
+        );
+        // Older failures start the count over
+        assert_eq!(
+            count_failed_signin(&failed(4, 20), now(), lockout, 5).attempts,
+            1
+        );
+    }
+
+    #[test]
+    fn locks_at_the_threshold() {
+        let lockout = Duration::minutes(15);
+        assert_eq!(
+            count_failed_signin(&failed(3, 1), now(), lockout, 5).locked_until,
+            None
+        );
+        assert_eq!(
+            count_failed_signin(&failed(4, 1), now(), lockout, 5),
+            FailedSignin {
+                attempts: 0,
+                locked_until: Some(now() + lockout),
+            }
+        );
+        // A threshold of 0 never locks
+        assert_eq!(
+            count_failed_signin(&failed(100, 1), now(), lockout, 0),
+            FailedSignin {
+                attempts: 101,
+                locked_until: None,
+            }
+        );
+    }
+
+    #[test]
+    fn locks_and_delays_expire() {
+        let locked = user::Model {
+            locked_until: Some(now() + Duration::minutes(5)),
+            ..account(None)
+        };
+        assert_eq!(signin_blocked_until(&locked, now()), locked.locked_until);
+        assert_eq!(
+            signin_blocked_until(&locked, now() + Duration::minutes(5)),
+            None
+        );
+
+        // The delay doubles with every failure, up to a minute
+        let last_failed = now() - Duration::seconds(1);
+        let after_three = user::Model {
+            last_failed_signin_at: Some(last_failed),
+            ..failed(3, 0)
+        };
+        assert_eq!(
+            signin_blocked_until(&after_three, now()),
+            Some(last_failed + Duration::seconds(4))
+        );
+        let after_ten = user::Model {
+            last_failed_signin_at: Some(last_failed),
+            ..failed(10, 0)
+        };
+        assert_eq!(
+            signin_blocked_until(&after_ten, now()),
+            Some(last_failed + Duration::seconds(MAX_SIGNIN_DELAY_SECS))
+        );
+        assert_eq!(
+            signin_blocked_until(&after_ten, now() + Duration::minutes(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn refuses_every_sign_in_with_the_same_error() {
+        let user = account(Some("correct horse"));
+        let locked = user::Model {
+            locked_until: Some(now() + Duration::minutes(5)),
+            ..user.clone()
+        };
+        let check = |user: Option<&user::Model>, password: &str| {
+            check_password_signin(user, password, now()).unwrap()
+        };
+
+        assert_eq!(
+            check(Some(&user), "correct horse"),
+            PasswordSignin::Accepted
+        );
+        assert!(PasswordSignin::Accepted.into_result().is_ok());
+
+        let refusals = [
+            (check(None, "correct horse"), PasswordSignin::UnknownEmail),
+            (check(Some(&user), "wrong"), PasswordSignin::WrongPassword),
+            (
+                check(Some(&account(None)), "correct horse"),
+                PasswordSignin::NoPassword,
+            ),
+            (
+                check(Some(&locked), "correct horse"),
+                PasswordSignin::Throttled,
+            ),
+        ];
+        for (outcome, expected) in refusals {
+            assert_eq!(outcome, expected);
+            let err = outcome.into_result().unwrap_err();
+            assert_eq!(err.message, INVALID_CREDENTIALS);
+            assert!(err.extensions.is_none());
+        }
+    }
+}
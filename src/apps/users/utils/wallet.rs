@@ -0,0 +1,458 @@
+use std::str::FromStr;
+
+use async_graphql::*;
+use candid::Principal;
+use chrono::{NaiveDateTime, Utc};
+use entity::entities::wallet_challenge;
+use ic_agent::{
+    hash_tree::{HashTree, LookupResult},
+    identity::Delegation,
+    Agent, Certificate,
+};
+use k256::ecdsa::signature::Verifier;
+use sea_orm::{entity::*, DatabaseConnection, QueryFilter};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    apps::{
+        assets::utils::identity::is_local_replica,
+        users::graphql::types::inputs::auth::WalletSignatureInput,
+    },
+    config::{settings::IcpSettings, state::AppState},
+};
+
+/// How long a challenge can be signed for
+pub const WALLET_CHALLENGE_TTL_SECS: u64 = 5 * 60;
+
+/// Maximum age of the certificate behind a canister signature, the longest
+/// delegation Internet Identity issues
+const CANISTER_SIG_MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// DER encoded `AlgorithmIdentifier` contents of the supported keys
+const ED25519_ALGORITHM: &[u8] = &[0x06, 0x03, 0x2b, 0x65, 0x70];
+const SECP256K1_ALGORITHM: &[u8] = &[
+    0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a,
+];
+/// Keys of canisters such as Internet Identity that sign through certified data
+const CANISTER_SIG_ALGORITHM: &[u8] = &[
+    0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xb8, 0x43, 0x01, 0x02,
+];
+
+#[derive(Deserialize)]
+struct CanisterSignature {
+    #[serde(with = "serde_bytes")]
+    certificate: Vec<u8>,
+    tree: HashTree<Vec<u8>>,
+}
+
+/// Issues a single use nonce for the client to sign
+pub async fn create_wallet_challenge(db: &DatabaseConnection) -> Result<wallet_challenge::Model> {
+    let uuid = Uuid::new_v4();
+    let expires_at =
+        (Utc::now() + std::time::Duration::from_secs(WALLET_CHALLENGE_TTL_SECS)).naive_utc();
+
+    let challenge = wallet_challenge::ActiveModel {
+        uuid: Set(uuid),
+        message: Set(challenge_message(&uuid, &expires_at)),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    };
+    Ok(challenge.insert(db).await?)
+}
+
+/// Deletes the challenges nobody signed in time, returning how many
+pub async fn delete_expired_challenges(db: &DatabaseConnection) -> Result<u64> {
+    let deleted = wallet_challenge::Entity::delete_many()
+        .filter(wallet_challenge::Column::ExpiresAt.lt(Utc::now().naive_utc()))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected)
+}
+
+fn challenge_message(nonce: &Uuid, expires_at: &NaiveDateTime) -> String {
+    format!(
+        "Sign in to Veecerts\n\nNonce: {}\nExpires at: {} UTC",
+        nonce, expires_at
+    )
+}
+
+/// Checks the signature of a challenge and returns the principal that signed
+/// it. The challenge is consumed whether or not the signature is valid.
+pub async fn verify_wallet_signature(
+    db: &DatabaseConnection,
+    state: &AppState,
+    input: &WalletSignatureInput,
+) -> Result<Principal> {
+    let challenge = wallet_challenge::Entity::find()
+        .filter(wallet_challenge::Column::Uuid.eq(Uuid::from_str(input.nonce.as_str())?))
+        .one(db)
+        .await?;
+    let challenge = match challenge {
+        Some(challenge) => challenge,
+        None => return Err(Error::new("Invalid or already used challenge")),
+    };
+    let consumed = wallet_challenge::Entity::delete_many()
+        .filter(wallet_challenge::Column::Uuid.eq(challenge.uuid))
+        .exec(db)
+        .await?;
+    // A concurrent sign in with the same nonce consumed it first
+    if consumed.rows_affected != 1 {
+        return Err(Error::new("Invalid or already used challenge"));
+    }
+
+    let message = challenge.message;
+    let expires_at = challenge.expires_at;
+    if expires_at <= Utc::now().naive_utc() {
+        return Err(Error::new("Challenge expired"));
+    }
+    let signature_agent = state.icp().ok().map(|icp| &icp.signature_agent);
+    verify_signed_message(message.as_bytes(), input, signature_agent)
+}
+
+/// Follows the delegation chain from `publicKey` and checks the signature of
+/// `message` with the key it ends at. Canister signatures can only be checked
+/// with the `signature_agent` of a configured ICP network.
+pub fn verify_signed_message(
+    message: &[u8],
+    input: &WalletSignatureInput,
+    signature_agent: Option<&Agent>,
+) -> Result<Principal> {
+    let public_key = decode_hex("publicKey", &input.public_key)?;
+    let now_ns = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX) as u64;
+    let mut signing_key = public_key.clone();
+    for signed in input.delegations.iter().flatten() {
+        // Such a delegation only speaks for the identity towards its canisters
+        if signed.targets.is_some() {
+            return Err(Error::new(
+                "Delegations restricted to canisters cannot be used to sign in",
+            ));
+        }
+        let delegation = Delegation {
+            pubkey: decode_hex("delegation pubkey", &signed.pubkey)?,
+            expiration: u64::from_str_radix(signed.expiration.trim_start_matches("0x"), 16)?,
+            targets: None,
+        };
+        if delegation.expiration <= now_ns {
+            return Err(Error::new("Delegation expired"));
+        }
+        verify_signature(
+            &signing_key,
+            &delegation.signable(),
+            &decode_hex("delegation signature", &signed.signature)?,
+            signature_agent,
+        )?;
+        signing_key = delegation.pubkey;
+    }
+
+    verify_signature(
+        &signing_key,
+        message,
+        &decode_hex("signature", &input.signature)?,
+        signature_agent,
+    )?;
+    Ok(Principal::self_authenticating(&public_key))
+}
+
+/// Verifies `signature` over `message` with a DER encoded public key the
+/// way the IC does: Ed25519, ECDSA secp256k1 over SHA-256 or a canister
+/// signature
+fn verify_signature(
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+    signature_agent: Option<&Agent>,
+) -> Result<()> {
+    let (algorithm, key) = match parse_public_key(public_key) {
+        Some(parsed) => parsed,
+        None => return Err(Error::new("Invalid DER encoded public key")),
+    };
+
+    let verified = if algorithm == ED25519_ALGORITHM {
+        let key = ed25519_consensus::VerificationKey::try_from(key)
+            .map_err(|_| Error::new("Invalid Ed25519 public key"))?;
+        let signature = ed25519_consensus::Signature::try_from(signature)
+            .map_err(|_| Error::new("Invalid Ed25519 signature"))?;
+        key.verify(&signature, message).is_ok()
+    } else if algorithm == SECP256K1_ALGORITHM {
+        let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(key)
+            .map_err(|_| Error::new("Invalid secp256k1 public key"))?;
+        let signature = k256::ecdsa::Signature::try_from(signature)
+            .map_err(|_| Error::new("Invalid secp256k1 signature"))?;
+        key.verify(message, &signature).is_ok()
+    } else if algorithm == CANISTER_SIG_ALGORITHM {
+        verify_canister_signature(key, message, signature, signature_agent)?
+    } else {
+        return Err(Error::new(
+            "Unsupported public key, expected Ed25519, secp256k1 or a canister signature key",
+        ));
+    };
+
+    if verified {
+        Ok(())
+    } else {
+        Err(Error::new("Invalid signature"))
+    }
+}
+
+/// A canister signature is a certificate whose certified data is the root
+/// of `tree`, which must hold `["sig", sha256(seed), sha256(message)]`
+fn verify_canister_signature(
+    key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+    signature_agent: Option<&Agent>,
+) -> Result<bool> {
+    let (canister_id, seed) = match key.split_first() {
+        Some((length, rest)) if rest.len() >= *length as usize => rest.split_at(*length as usize),
+        _ => return Err(Error::new("Invalid canister signature public key")),
+    };
+    let canister_id = Principal::try_from_slice(canister_id)?;
+
+    let signature: CanisterSignature =
+        serde_cbor::from_slice(signature).map_err(|_| Error::new("Invalid canister signature"))?;
+    let certificate: Certificate = serde_cbor::from_slice(&signature.certificate)
+        .map_err(|_| Error::new("Invalid canister signature certificate"))?;
+
+    let agent = match signature_agent {
+        Some(agent) => agent,
+        None => {
+            return Err(Error::new(
+                "ICP is not configured to verify canister signatures",
+            ))
+        }
+    };
+    if agent.verify(&certificate, canister_id).is_err() {
+        return Ok(false);
+    }
+
+    let certified_data = certificate.tree.lookup_path([
+        "canister".as_bytes(),
+        canister_id.as_slice(),
+        "certified_data".as_bytes(),
+    ]);
+    if certified_data != LookupResult::Found(&signature.tree.digest()) {
+        return Ok(false);
+    }
+
+    let signed = signature.tree.lookup_path([
+        "sig".as_bytes(),
+        &Sha256::digest(seed),
+        &Sha256::digest(message),
+    ]);
+    Ok(signed == LookupResult::Found(&[]))
+}
+
+/// Agent checking canister signature certificates of the network, accepting
+/// certificates as old as the longest delegation
+pub async fn canister_signature_agent(icp: &IcpSettings) -> Result<Agent> {
+    let agent = Agent::builder()
+        .with_url(icp.agent_endpoint.clone())
+        .with_ingress_expiry(std::time::Duration::from_secs(CANISTER_SIG_MAX_AGE_SECS))
+        .build()?;
+    // Only a local replica's root key may be fetched, mainnet's is built in
+    if is_local_replica(&icp.agent_endpoint) {
+        agent.fetch_root_key().await?;
+    }
+    Ok(agent)
+}
+
+/// Splits a DER `SubjectPublicKeyInfo` into the contents of its algorithm
+/// identifier and the key bits
+fn parse_public_key(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (info, _) = read_der(0x30, der)?;
+    let (algorithm, rest) = read_der(0x30, info)?;
+    let (bits, _) = read_der(0x03, rest)?;
+    // The first byte counts the unused bits, always zero for keys
+    match bits.split_first() {
+        Some((0, key)) => Some((algorithm, key)),
+        _ => None,
+    }
+}
+
+/// Reads one DER element with `tag`, returning its contents and what follows
+fn read_der(tag: u8, bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (first, rest) = bytes.split_first()?;
+    if *first != tag {
+        return None;
+    }
+    let (length, rest) = match rest.split_first()? {
+        (length, rest) if *length < 0x80 => (*length as usize, rest),
+        (length, rest) => {
+            let count = (*length & 0x7f) as usize;
+            if count == 0 || count > 2 || rest.len() < count {
+                return None;
+            }
+            let (digits, rest) = rest.split_at(count);
+            let length = digits
+                .iter()
+                .fold(0usize, |total, digit| (total << 8) | *digit as usize);
+            (length, rest)
+        }
+    };
+    if rest.len() < length {
+        return None;
+    }
+    Some(rest.split_at(length))
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|err| Error::new(format!("Invalid hex in {}: {}", field, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_agent::{
+        identity::{BasicIdentity, Secp256k1Identity},
+        Identity,
+    };
+
+    use super::*;
+    use crate::apps::users::graphql::types::inputs::auth::SignedDelegationInput;
+
+    const MESSAGE: &[u8] = b"Sign in to Veecerts\n\nNonce: test";
+
+    fn ed25519_identity(seed: u8) -> BasicIdentity {
+        BasicIdentity::from_signing_key(ed25519_consensus::SigningKey::from([seed; 32]))
+    }
+
+    fn secp256k1_identity(seed: u8) -> Secp256k1Identity {
+        Secp256k1Identity::from_private_key(k256::SecretKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    fn public_key(identity: &impl Identity) -> Vec<u8> {
+        identity.public_key().unwrap()
+    }
+
+    fn signed_input(identity: &impl Identity, message: &[u8]) -> WalletSignatureInput {
+        WalletSignatureInput {
+            nonce: String::new(),
+            public_key: hex::encode(public_key(identity)),
+            signature: hex::encode(identity.sign_arbitrary(message).unwrap().signature.unwrap()),
+            delegations: None,
+        }
+    }
+
+    /// `root` delegating to `session` until `expiration`, in nanoseconds
+    fn delegation(
+        root: &impl Identity,
+        session: &impl Identity,
+        expiration: u64,
+    ) -> SignedDelegationInput {
+        let delegation = Delegation {
+            pubkey: public_key(session),
+            expiration,
+            targets: None,
+        };
+        SignedDelegationInput {
+            pubkey: hex::encode(&delegation.pubkey),
+            expiration: format!("{:x}", expiration),
+            targets: None,
+            signature: hex::encode(
+                root.sign_delegation(&delegation)
+                    .unwrap()
+                    .signature
+                    .unwrap(),
+            ),
+        }
+    }
+
+    fn in_an_hour() -> u64 {
+        (Utc::now() + std::time::Duration::from_secs(60 * 60))
+            .timestamp_nanos_opt()
+            .unwrap() as u64
+    }
+
+    #[test]
+    fn accepts_ed25519_signatures() {
+        let identity = ed25519_identity(1);
+        let principal =
+            verify_signed_message(MESSAGE, &signed_input(&identity, MESSAGE), None).unwrap();
+        assert_eq!(principal, identity.sender().unwrap());
+    }
+
+    #[test]
+    fn accepts_secp256k1_signatures() {
+        let identity = secp256k1_identity(1);
+        let principal =
+            verify_signed_message(MESSAGE, &signed_input(&identity, MESSAGE), None).unwrap();
+        assert_eq!(principal, identity.sender().unwrap());
+    }
+
+    #[test]
+    fn signs_in_as_the_root_of_a_delegation_chain() {
+        let root = secp256k1_identity(2);
+        let session = ed25519_identity(3);
+        let mut input = signed_input(&session, MESSAGE);
+        input.public_key = hex::encode(public_key(&root));
+        input.delegations = Some(vec![delegation(&root, &session, in_an_hour())]);
+
+        let principal = verify_signed_message(MESSAGE, &input, None).unwrap();
+        assert_eq!(principal, root.sender().unwrap());
+    }
+
+    #[test]
+    fn rejects_expired_delegations() {
+        let root = secp256k1_identity(2);
+        let session = ed25519_identity(3);
+        let expired = (Utc::now().timestamp_nanos_opt().unwrap() - 1) as u64;
+        let mut input = signed_input(&session, MESSAGE);
+        input.public_key = hex::encode(public_key(&root));
+        input.delegations = Some(vec![delegation(&root, &session, expired)]);
+
+        let err = verify_signed_message(MESSAGE, &input, None).unwrap_err();
+        assert_eq!(err.message, "Delegation expired");
+    }
+
+    #[test]
+    fn rejects_delegations_restricted_to_canisters() {
+        let root = secp256k1_identity(2);
+        let session = ed25519_identity(3);
+        let mut signed = delegation(&root, &session, in_an_hour());
+        signed.targets = Some(vec![hex::encode(Principal::management_canister())]);
+        let mut input = signed_input(&session, MESSAGE);
+        input.public_key = hex::encode(public_key(&root));
+        input.delegations = Some(vec![signed]);
+
+        assert!(verify_signed_message(MESSAGE, &input, None).is_err());
+    }
+
+    #[test]
+    fn rejects_signatures_of_another_message() {
+        for input in [
+            signed_input(&ed25519_identity(4), b"another message"),
+            signed_input(&secp256k1_identity(4), b"another message"),
+        ] {
+            let err = verify_signed_message(MESSAGE, &input, None).unwrap_err();
+            assert_eq!(err.message, "Invalid signature");
+        }
+    }
+
+    #[test]
+    fn rejects_signatures_by_another_key() {
+        let mut input = signed_input(&ed25519_identity(5), MESSAGE);
+        input.public_key = hex::encode(public_key(&ed25519_identity(6)));
+
+        let err = verify_signed_message(MESSAGE, &input, None).unwrap_err();
+        assert_eq!(err.message, "Invalid signature");
+    }
+
+    #[test]
+    fn rejects_malformed_der_keys() {
+        let identity = ed25519_identity(7);
+        let der = public_key(&identity);
+        for malformed in [
+            Vec::new(),
+            der[..der.len() - 1].to_vec(),
+            [&[0x31], &der[1..]].concat(),
+            [&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff], &der[2..]].concat(),
+        ] {
+            let mut input = signed_input(&identity, MESSAGE);
+            input.public_key = hex::encode(&malformed);
+            let err = verify_signed_message(MESSAGE, &input, None).unwrap_err();
+            assert_eq!(err.message, "Invalid DER encoded public key");
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use sea_orm::*;
 
-use super::settings::ENV;
+use super::telemetry::trace_queries;
 
-pub async fn connect_db() -> Result<DatabaseConnection, DbErr> {
-    let db_url = ENV::init().db_url;
+pub async fn connect_db(db_url: &str) -> Result<DatabaseConnection, DbErr> {
     let mut opts = ConnectOptions::new(db_url);
-    opts.sqlx_logging(true)
-        .sqlx_logging_level(log::LevelFilter::Info);
+    // Statements are logged by `trace_queries`, without their bound values
+    opts.sqlx_logging(false);
 
-    Database::connect(opts).await
+    let mut db = Database::connect(opts).await?;
+    trace_queries(&mut db);
+    Ok(db)
 }
@@ -0,0 +1,38 @@
+use std::{sync::Arc, time::Duration};
+
+use async_graphql::Result;
+use sea_orm::DatabaseConnection;
+
+use super::{rate_limit::RateLimiter, state::AppState};
+use crate::apps::{
+    assets::utils::exports::expire_exports, users::utils::wallet::delete_expired_challenges,
+};
+
+/// Time between two rounds of clean up
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Deletes what outlived its use, expired exports and wallet challenges and
+/// full rate limit buckets, on an interval for as long as the server runs
+pub fn spawn_housekeeping(
+    db: DatabaseConnection,
+    state: Arc<AppState>,
+    rate_limiter: Arc<RateLimiter>,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(HOUSEKEEPING_INTERVAL);
+        loop {
+            interval.tick().await;
+            report("exports", expire_exports(&db, &state).await);
+            report("wallet_challenges", delete_expired_challenges(&db).await);
+            report("rate_limit_buckets", rate_limiter.sweep().await);
+        }
+    });
+}
+
+fn report(task: &str, result: Result<u64>) {
+    match result {
+        Ok(0) => {}
+        Ok(removed) => tracing::info!(task, removed, "Removed expired records"),
+        Err(err) => tracing::warn!(task, error = %err.message, "Expired records were not removed"),
+    }
+}
@@ -0,0 +1,29 @@
+use async_graphql::*;
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::settings::SmtpSettings;
+
+/// Sends plain text emails through the SMTP server of `SMTP_URL`
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(settings: &SmtpSettings) -> Result<Mailer> {
+        Ok(Mailer {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(&settings.url)?.build(),
+            from: settings.from.parse()?,
+        })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .body(body)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
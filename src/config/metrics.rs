@@ -0,0 +1,443 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    future::Future,
+    sync::OnceLock,
+    time::Instant,
+};
+
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse};
+use async_graphql::{
+    parser::{
+        parse_schema,
+        types::{
+            DocumentOperations, ExecutableDocument, OperationType, Selection, SelectionSet,
+            TypeKind, TypeSystemDefinition,
+        },
+    },
+    OutputType, Request,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
+
+use super::{
+    schema::{AppSchema, Mutation, Query},
+    state::AppState,
+};
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Process wide Prometheus metrics. Labels only ever hold root field names,
+/// content categories and service names, never anything about a user.
+pub struct Metrics {
+    registry: Registry,
+    pub graphql_requests: IntCounterVec,
+    pub graphql_request_duration: HistogramVec,
+    pub uploads: IntCounterVec,
+    pub upload_bytes: IntCounterVec,
+    pub external_call_duration: HistogramVec,
+    pub external_call_errors: IntCounterVec,
+    pub db_pool_connections: IntGaugeVec,
+    pub quota_rejections: IntCounterVec,
+    pub rate_limited: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn get() -> &'static Metrics {
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some(String::from("veecerts")), None)
+            .expect("Metrics registry prefix is valid");
+        let metrics = Metrics {
+            graphql_requests: IntCounterVec::new(
+                Opts::new("graphql_requests_total", "GraphQL requests by root field"),
+                &["operation", "status"],
+            )
+            .expect("Metric is valid"),
+            graphql_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "graphql_request_duration_seconds",
+                    "Time spent executing GraphQL requests",
+                ),
+                &["operation"],
+            )
+            .expect("Metric is valid"),
+            uploads: IntCounterVec::new(
+                Opts::new("uploads_total", "Stored uploads by content category"),
+                &["category"],
+            )
+            .expect("Metric is valid"),
+            upload_bytes: IntCounterVec::new(
+                Opts::new("upload_bytes_total", "Bytes of stored uploads"),
+                &["category"],
+            )
+            .expect("Metric is valid"),
+            external_call_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "external_call_duration_seconds",
+                    "Latency of Pinata and ICP calls",
+                ),
+                &["service", "method"],
+            )
+            .expect("Metric is valid"),
+            external_call_errors: IntCounterVec::new(
+                Opts::new("external_call_errors_total", "Failed Pinata and ICP calls"),
+                &["service", "method"],
+            )
+            .expect("Metric is valid"),
+            db_pool_connections: IntGaugeVec::new(
+                Opts::new("db_pool_connections", "Database pool connections by state"),
+                &["state"],
+            )
+            .expect("Metric is valid"),
+            quota_rejections: IntCounterVec::new(
+                Opts::new(
+                    "quota_rejections_total",
+                    "Uploads and folders refused by package limits",
+                ),
+                &["reason"],
+            )
+            .expect("Metric is valid"),
+            rate_limited: IntCounterVec::new(
+                Opts::new("rate_limited_total", "Requests refused by a rate limit"),
+                &["scope"],
+            )
+            .expect("Metric is valid"),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 9] = [
+            Box::new(metrics.graphql_requests.clone()),
+            Box::new(metrics.graphql_request_duration.clone()),
+            Box::new(metrics.uploads.clone()),
+            Box::new(metrics.upload_bytes.clone()),
+            Box::new(metrics.external_call_duration.clone()),
+            Box::new(metrics.external_call_errors.clone()),
+            Box::new(metrics.db_pool_connections.clone()),
+            Box::new(metrics.quota_rejections.clone()),
+            Box::new(metrics.rate_limited.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Metric is registered once");
+        }
+        metrics
+    }
+}
+
+/// Times a call to an external service in its own span, counting it as
+/// failed when it errors
+pub async fn observe_call<T, E: fmt::Display>(
+    service: &str,
+    method: &str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let metrics = Metrics::get();
+    let started = Instant::now();
+    let span = tracing::info_span!("external_call", service, method);
+    let result = call.instrument(span.clone()).await;
+    if let Err(err) = &result {
+        span.in_scope(|| tracing::warn!(error = %err, "call failed"));
+    }
+    metrics
+        .external_call_duration
+        .with_label_values(&[service, method])
+        .observe(started.elapsed().as_secs_f64());
+    if result.is_err() {
+        metrics
+            .external_call_errors
+            .with_label_values(&[service, method])
+            .inc();
+    }
+    result
+}
+
+/// Counts a stored upload under the category of its content type
+pub fn record_upload(content_type: &str, bytes: u64) {
+    let metrics = Metrics::get();
+    let category = content_category(content_type);
+    metrics.uploads.with_label_values(&[category]).inc();
+    metrics
+        .upload_bytes
+        .with_label_values(&[category])
+        .inc_by(bytes);
+}
+
+pub fn record_quota_rejection(reason: &str) {
+    Metrics::get()
+        .quota_rejections
+        .with_label_values(&[reason])
+        .inc();
+}
+
+pub fn record_rate_limited(scope: &str) {
+    Metrics::get()
+        .rate_limited
+        .with_label_values(&[scope])
+        .inc();
+}
+
+/// Root fields of the schema, the only names GraphQL requests are labelled
+/// by, so whatever clients name their operations or select does not grow
+/// the number of series
+pub struct OperationLabels {
+    queries: HashSet<String>,
+    mutations: HashSet<String>,
+}
+
+impl OperationLabels {
+    pub fn new(schema: &AppSchema) -> OperationLabels {
+        let mut labels = OperationLabels {
+            queries: HashSet::new(),
+            mutations: HashSet::new(),
+        };
+        let document = match parse_schema(schema.sdl()) {
+            Ok(document) => document,
+            Err(_) => return labels,
+        };
+        let query_type = Query::type_name();
+        let mutation_type = Mutation::type_name();
+        for definition in document.definitions {
+            let definition = match definition {
+                TypeSystemDefinition::Type(definition) => definition.node,
+                _ => continue,
+            };
+            let object = match definition.kind {
+                TypeKind::Object(object) => object,
+                _ => continue,
+            };
+            let fields = object
+                .fields
+                .into_iter()
+                .map(|field| field.node.name.node.to_string());
+            if definition.name.node.as_str() == query_type {
+                labels.queries.extend(fields);
+            } else if definition.name.node.as_str() == mutation_type {
+                labels.mutations.extend(fields);
+            }
+        }
+        labels
+    }
+
+    /// The label of a GraphQL request: the root field the operation it runs
+    /// selects, `multiple` when it selects several, `other` for anything the
+    /// schema does not define and `invalid` when it does not parse. Parsing
+    /// here is reused when the request executes.
+    pub fn label(&self, request: &mut Request) -> String {
+        let operation_name = request.operation_name.clone();
+        let document = match request.parsed_query() {
+            Ok(document) => document,
+            Err(_) => return String::from("invalid"),
+        };
+        let operation = match (&document.operations, operation_name) {
+            (DocumentOperations::Single(operation), None) => operation,
+            (DocumentOperations::Multiple(operations), Some(name)) => {
+                match operations.get(name.as_str()) {
+                    Some(operation) => operation,
+                    None => return String::from("invalid"),
+                }
+            }
+            (DocumentOperations::Multiple(operations), None) if operations.len() == 1 => {
+                match operations.values().next() {
+                    Some(operation) => operation,
+                    None => return String::from("invalid"),
+                }
+            }
+            _ => return String::from("invalid"),
+        };
+        let known = match operation.node.ty {
+            OperationType::Query => &self.queries,
+            OperationType::Mutation => &self.mutations,
+            OperationType::Subscription => return String::from("other"),
+        };
+
+        let mut fields = BTreeSet::new();
+        let mut spread = Vec::new();
+        if !root_fields(
+            document,
+            &operation.node.selection_set.node,
+            &mut spread,
+            &mut fields,
+        ) {
+            return String::from("other");
+        }
+        if fields.is_empty() || !fields.iter().all(|field| known.contains(*field)) {
+            return String::from("other");
+        }
+        match fields.len() {
+            1 => fields.into_iter().map(String::from).collect(),
+            _ => String::from("multiple"),
+        }
+    }
+}
+
+/// Collects the fields a selection set selects, following fragments. False
+/// when it spreads a fragment the document does not define or spreads one
+/// inside itself.
+fn root_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    spread: &mut Vec<&'a str>,
+    fields: &mut BTreeSet<&'a str>,
+) -> bool {
+    for selection in &selection_set.items {
+        let followed = match &selection.node {
+            Selection::Field(field) => {
+                fields.insert(field.node.name.node.as_str());
+                true
+            }
+            Selection::InlineFragment(fragment) => {
+                root_fields(document, &fragment.node.selection_set.node, spread, fields)
+            }
+            Selection::FragmentSpread(fragment_spread) => {
+                let name = fragment_spread.node.fragment_name.node.as_str();
+                let fragment = match document.fragments.get(name) {
+                    Some(fragment) if !spread.contains(&name) => fragment,
+                    _ => return false,
+                };
+                spread.push(name);
+                let followed =
+                    root_fields(document, &fragment.node.selection_set.node, spread, fields);
+                spread.pop();
+                followed
+            }
+        };
+        if !followed {
+            return false;
+        }
+    }
+    true
+}
+
+/// The categories of the storage summary
+fn content_category(content_type: &str) -> &'static str {
+    if content_type.starts_with("image") {
+        "image"
+    } else if content_type.starts_with("video") {
+        "video"
+    } else if content_type.starts_with("audio") {
+        "audio"
+    } else if content_type.starts_with("application") || content_type.starts_with("text") {
+        "document"
+    } else {
+        "other"
+    }
+}
+
+/// Whether the request carries `METRICS_TOKEN` as its bearer token, compared
+/// by digest so the time taken says nothing about the token
+fn authorized(req: &HttpRequest, token: &str) -> bool {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| Sha256::digest(given) == Sha256::digest(token))
+}
+
+/// Served only with `METRICS_TOKEN` set, to scrapers sending it as a bearer
+/// token
+#[get("/metrics")]
+pub async fn prometheus_metrics(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let token = match &state.env.metrics_token {
+        Some(token) => token,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    if !authorized(&req, token) {
+        return HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .finish();
+    }
+
+    let metrics = Metrics::get();
+    let pool = db.get_postgres_connection_pool();
+    let idle = pool.num_idle() as i64;
+    let gauges = &metrics.db_pool_connections;
+    gauges.with_label_values(&["idle"]).set(idle);
+    gauges
+        .with_label_values(&["active"])
+        .set(pool.size() as i64 - idle);
+    gauges
+        .with_label_values(&["max"])
+        .set(pool.options().get_max_connections() as i64);
+
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    match encoder.encode(&metrics.registry.gather(), &mut body) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptySubscription, Schema};
+
+    use super::*;
+
+    fn label(query: &str, operation_name: Option<&str>) -> String {
+        let schema =
+            Schema::build(Query::default(), Mutation::default(), EmptySubscription).finish();
+        let mut request = Request::new(query);
+        if let Some(name) = operation_name {
+            request = request.operation_name(name);
+        }
+        OperationLabels::new(&schema).label(&mut request)
+    }
+
+    #[test]
+    fn labels_by_root_field() {
+        assert_eq!(label("{ user { id } }", None), "user");
+        assert_eq!(
+            label(
+                "mutation RandomName123 { refreshToken(token: \"x\") }",
+                None
+            ),
+            "refreshToken"
+        );
+        assert_eq!(
+            label(
+                "query A { user { id } } query B { clientFolders { id } }",
+                Some("B")
+            ),
+            "clientFolders"
+        );
+        assert_eq!(
+            label(
+                "query { ...Root } fragment Root on Query { user { id } }",
+                None
+            ),
+            "user"
+        );
+        assert_eq!(
+            label("{ user { id } clientFolders { id } }", None),
+            "multiple"
+        );
+    }
+
+    #[test]
+    fn groups_what_the_schema_does_not_define() {
+        assert_eq!(label("{ somethingMadeUp }", None), "other");
+        assert_eq!(label("mutation { user { id } }", None), "other");
+        assert_eq!(label("{ __typename }", None), "other");
+        assert_eq!(
+            label("query { ...A } fragment A on Query { ...A }", None),
+            "other"
+        );
+        assert_eq!(label("{ user {", None), "invalid");
+        assert_eq!(label("query A { user { id } }", Some("B")), "invalid");
+    }
+}
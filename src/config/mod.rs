@@ -1,3 +1,10 @@
 pub mod database;
+pub mod health;
+pub mod housekeeping;
+pub mod mailer;
+pub mod metrics;
+pub mod rate_limit;
 pub mod schema;
 pub mod settings;
+pub mod state;
+pub mod telemetry;
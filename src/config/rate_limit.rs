@@ -0,0 +1,525 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::HttpRequest;
+use async_graphql::*;
+use async_trait::async_trait;
+use entity::entities::rate_limit_bucket;
+use sea_orm::{
+    entity::*, sea_query::OnConflict, DatabaseConnection, QueryFilter, QuerySelect,
+    TransactionTrait,
+};
+
+use super::{
+    metrics::record_rate_limited,
+    settings::{RateLimit, RateLimitBackend, RateLimitSettings},
+};
+
+/// Buckets the memory store keeps, the one closest to full makes room for
+/// a new key past it
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// Address of the caller, passed to every GraphQL request
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl ClientIp {
+    pub fn of(req: &HttpRequest, trust_forwarded: bool) -> ClientIp {
+        let addr = if trust_forwarded {
+            req.connection_info().realip_remote_addr().and_then(|addr| {
+                addr.parse::<SocketAddr>()
+                    .map(|addr| addr.ip())
+                    .or_else(|_| addr.parse::<IpAddr>())
+                    .ok()
+            })
+        } else {
+            req.peer_addr().map(|addr| addr.ip())
+        };
+        ClientIp(addr)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum RateLimitScope {
+    AuthIp,
+    AuthEmail,
+    UploadClient,
+}
+
+impl RateLimitScope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RateLimitScope::AuthIp => "auth_ip",
+            RateLimitScope::AuthEmail => "auth_email",
+            RateLimitScope::UploadClient => "upload_client",
+        }
+    }
+}
+
+/// Refills a bucket holding `tokens` for the `elapsed` time and takes `cost`
+/// from it. Returns the new balance and, when the bucket was short, how long
+/// until it holds enough. `cost` is at most the capacity, `check` refuses
+/// larger ones.
+fn refill_and_take(
+    limit: &RateLimit,
+    tokens: f64,
+    elapsed: Duration,
+    cost: f64,
+) -> (f64, Option<Duration>) {
+    let capacity = limit.capacity as f64;
+    let rate = capacity / limit.period.as_secs_f64();
+    let tokens = (tokens + elapsed.as_secs_f64() * rate).min(capacity);
+
+    if tokens >= cost {
+        (tokens - cost, None)
+    } else {
+        (
+            tokens,
+            Some(Duration::from_secs_f64((cost - tokens) / rate)),
+        )
+    }
+}
+
+/// How long until a bucket holding `tokens` is full again
+fn time_to_full(limit: &RateLimit, tokens: f64) -> Duration {
+    let capacity = limit.capacity as f64;
+    Duration::from_secs_f64((capacity - tokens).max(0.0) * limit.period.as_secs_f64() / capacity)
+}
+
+/// Keeps the token buckets. `take` returns `None` when the tokens were
+/// taken, or how long to wait before retrying. `sweep` drops the buckets that
+/// are full again, which a missing bucket stands for, returning how many.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, limit: &RateLimit, cost: f64) -> Result<Option<Duration>>;
+    async fn sweep(&self) -> Result<u64>;
+}
+
+struct MemoryBucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+/// Buckets by key, indexed by when they are full again so the full ones are
+/// found without going through every bucket
+#[derive(Default)]
+struct MemoryBuckets {
+    buckets: HashMap<String, MemoryBucket>,
+    by_full_at: BTreeSet<(Instant, String)>,
+}
+
+impl MemoryBuckets {
+    fn remove(&mut self, key: &str) -> Option<MemoryBucket> {
+        let bucket = self.buckets.remove(key)?;
+        self.by_full_at.remove(&(bucket.full_at, key.to_string()));
+        Some(bucket)
+    }
+
+    fn insert(&mut self, key: String, bucket: MemoryBucket) {
+        self.by_full_at.insert((bucket.full_at, key.clone()));
+        self.buckets.insert(key, bucket);
+    }
+
+    /// Drops the buckets closest to full until there are fewer than `max`
+    fn make_room(&mut self, max: usize) {
+        while self.buckets.len() >= max {
+            match self.by_full_at.pop_first() {
+                Some((_, key)) => self.buckets.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    fn remove_full(&mut self, now: Instant) -> u64 {
+        let filling = self.by_full_at.split_off(&(now, String::new()));
+        let full = std::mem::replace(&mut self.by_full_at, filling);
+        for (_, key) in &full {
+            self.buckets.remove(key);
+        }
+        full.len() as u64
+    }
+}
+
+pub struct MemoryStore {
+    buckets: Mutex<MemoryBuckets>,
+    max_buckets: usize,
+}
+
+impl MemoryStore {
+    pub fn new(max_buckets: usize) -> MemoryStore {
+        MemoryStore {
+            buckets: Mutex::new(MemoryBuckets::default()),
+            max_buckets,
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore::new(MAX_MEMORY_BUCKETS)
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: &RateLimit, cost: f64) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+
+        let (tokens, elapsed) = match buckets.remove(key) {
+            Some(bucket) => (bucket.tokens, now.duration_since(bucket.updated_at)),
+            None => {
+                buckets.make_room(self.max_buckets);
+                (limit.capacity as f64, Duration::ZERO)
+            }
+        };
+        let (tokens, retry_after) = refill_and_take(limit, tokens, elapsed, cost);
+        buckets.insert(
+            key.to_string(),
+            MemoryBucket {
+                tokens,
+                updated_at: now,
+                full_at: now + time_to_full(limit, tokens),
+            },
+        );
+        Ok(retry_after)
+    }
+
+    async fn sweep(&self) -> Result<u64> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(buckets.remove_full(Instant::now()))
+    }
+}
+
+/// Buckets in the `rate_limit_bucket` table, each taken under a row lock so
+/// concurrent instances see every other's tokens
+pub struct PostgresStore {
+    db: DatabaseConnection,
+}
+
+impl PostgresStore {
+    pub fn new(db: DatabaseConnection) -> PostgresStore {
+        PostgresStore { db }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(&self, key: &str, limit: &RateLimit, cost: f64) -> Result<Option<Duration>> {
+        let now = chrono::Utc::now().naive_utc();
+        let txn = self.db.begin().await?;
+
+        rate_limit_bucket::Entity::insert(rate_limit_bucket::ActiveModel {
+            key: Set(key.to_string()),
+            tokens: Set(limit.capacity as f64),
+            updated_at: Set(now),
+            expires_at: Set(now),
+        })
+        .on_conflict(
+            OnConflict::column(rate_limit_bucket::Column::Key)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(&txn)
+        .await?;
+
+        let bucket = rate_limit_bucket::Entity::find_by_id(key)
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+        let retry_after = if let Some(bucket) = bucket {
+            let elapsed = (now - bucket.updated_at).to_std().unwrap_or_default();
+            let (tokens, retry_after) = refill_and_take(limit, bucket.tokens, elapsed, cost);
+
+            let full_in = chrono::Duration::from_std(time_to_full(limit, tokens));
+
+            let mut bucket: rate_limit_bucket::ActiveModel = bucket.into();
+            bucket.tokens = Set(tokens);
+            bucket.updated_at = Set(now);
+            bucket.expires_at = Set(now + full_in.unwrap_or_default());
+            bucket.update(&txn).await?;
+            retry_after
+        } else {
+            None
+        };
+        txn.commit().await?;
+        Ok(retry_after)
+    }
+
+    async fn sweep(&self) -> Result<u64> {
+        let result = rate_limit_bucket::Entity::delete_many()
+            .filter(rate_limit_bucket::Column::ExpiresAt.lt(chrono::Utc::now().naive_utc()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+/// Applies the limits of `RATE_LIMIT_*`, shared by every worker through the
+/// schema data
+pub struct RateLimiter {
+    store: Box<dyn RateLimitStore>,
+    auth_ip: Option<RateLimit>,
+    auth_email: Option<RateLimit>,
+    upload_client: Option<RateLimit>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings, db: DatabaseConnection) -> RateLimiter {
+        let store: Box<dyn RateLimitStore> = match settings.store {
+            RateLimitBackend::Memory => Box::new(MemoryStore::default()),
+            RateLimitBackend::Postgres => Box::new(PostgresStore::new(db)),
+        };
+        RateLimiter {
+            store,
+            auth_ip: settings.auth_ip,
+            auth_email: settings.auth_email,
+            upload_client: settings.upload_client,
+        }
+    }
+
+    /// Takes `cost` tokens from the bucket of `key` in `scope`, failing with
+    /// a `RATE_LIMITED` error and a `Retry-After` header once it is empty.
+    /// A cost over the capacity would never fit and fails with
+    /// `BATCH_TOO_LARGE` without touching the bucket.
+    pub async fn check(
+        &self,
+        ctx: &Context<'_>,
+        scope: RateLimitScope,
+        key: &str,
+        cost: u32,
+    ) -> Result<()> {
+        let limit = match scope {
+            RateLimitScope::AuthIp => self.auth_ip,
+            RateLimitScope::AuthEmail => self.auth_email,
+            RateLimitScope::UploadClient => self.upload_client,
+        };
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        if cost > limit.capacity {
+            record_rate_limited(scope.name());
+            return Err(Error::new(format!(
+                "At most {} items can be sent at once",
+                limit.capacity
+            ))
+            .extend_with(|_, ext| {
+                ext.set("code", "BATCH_TOO_LARGE");
+                ext.set("scope", scope.name());
+                ext.set("limit", limit.capacity);
+                ext.set("periodSeconds", limit.period.as_secs());
+            }));
+        }
+
+        let bucket_key = format!("{}:{}", scope.name(), key);
+        let retry_after = match self.store.take(&bucket_key, &limit, cost as f64).await {
+            Ok(retry_after) => retry_after,
+            Err(err) => {
+                // An unavailable store must not take the API down with it
+                tracing::warn!(scope = scope.name(), error = %err.message, "Rate limit not checked");
+                None
+            }
+        };
+
+        if let Some(retry_after) = retry_after {
+            let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            record_rate_limited(scope.name());
+            ctx.insert_http_header("Retry-After", retry_after_secs.to_string());
+            return Err(Error::new(format!(
+                "Too many requests, try again in {} seconds",
+                retry_after_secs
+            ))
+            .extend_with(|_, ext| {
+                ext.set("code", "RATE_LIMITED");
+                ext.set("scope", scope.name());
+                ext.set("retryAfter", retry_after_secs);
+                ext.set("limit", limit.capacity);
+                ext.set("periodSeconds", limit.period.as_secs());
+            }));
+        }
+        Ok(())
+    }
+
+    /// Drops the buckets that are full again
+    pub async fn sweep(&self) -> Result<u64> {
+        self.store.sweep().await
+    }
+
+    /// Limits authentication attempts from the caller's address
+    pub async fn check_ip(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(ClientIp(Some(ip))) = ctx.data_opt::<ClientIp>() {
+            self.check(ctx, RateLimitScope::AuthIp, &ip.to_string(), 1)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Limits the uploads of a client. A batch or an archive counts as one
+    /// call whatever its size, which `MAX_BATCH_UPLOAD_FILES` bounds instead.
+    pub async fn check_upload(&self, ctx: &Context<'_>, client_id: i32) -> Result<()> {
+        self.check(ctx, RateLimitScope::UploadClient, &client_id.to_string(), 1)
+            .await
+    }
+
+    /// Limits authentication attempts for an email, however it is cased
+    pub async fn check_email(&self, ctx: &Context<'_>, email: &str) -> Result<()> {
+        self.check(
+            ctx,
+            RateLimitScope::AuthEmail,
+            &email.trim().to_lowercase(),
+            1,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Schema};
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::config::settings::ENV;
+
+    fn limit(capacity: u32, period_secs: u64) -> RateLimit {
+        RateLimit {
+            capacity,
+            period: Duration::from_secs(period_secs),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn takes_and_refills_tokens() {
+        let limit = limit(10, 10);
+        let (tokens, retry_after) = refill_and_take(&limit, 10.0, Duration::ZERO, 3.0);
+        assert_close(tokens, 7.0);
+        assert!(retry_after.is_none());
+
+        // One token a second
+        let (tokens, retry_after) = refill_and_take(&limit, 0.0, Duration::from_secs(4), 1.0);
+        assert_close(tokens, 3.0);
+        assert!(retry_after.is_none());
+    }
+
+    #[test]
+    fn refills_no_more_than_the_capacity() {
+        let limit = limit(10, 10);
+        let (tokens, _) = refill_and_take(&limit, 5.0, Duration::from_secs(3600), 0.0);
+        assert_close(tokens, 10.0);
+        let (tokens, retry_after) = refill_and_take(&limit, 5.0, Duration::from_secs(3600), 10.0);
+        assert_close(tokens, 0.0);
+        assert!(retry_after.is_none());
+    }
+
+    #[test]
+    fn short_buckets_are_left_alone_with_a_retry_after() {
+        let limit = limit(10, 10);
+        let (tokens, retry_after) = refill_and_take(&limit, 1.0, Duration::from_millis(500), 4.0);
+        assert_close(tokens, 1.5);
+        assert_close(retry_after.unwrap().as_secs_f64(), 2.5);
+    }
+
+    #[test]
+    fn time_to_full_follows_the_refill_rate() {
+        let limit = limit(10, 60);
+        assert_eq!(time_to_full(&limit, 10.0), Duration::ZERO);
+        assert_eq!(time_to_full(&limit, 12.0), Duration::ZERO);
+        assert_close(time_to_full(&limit, 0.0).as_secs_f64(), 60.0);
+        assert_close(time_to_full(&limit, 7.5).as_secs_f64(), 15.0);
+    }
+
+    #[actix_web::test]
+    async fn memory_store_makes_room_for_new_keys() {
+        let store = MemoryStore::new(2);
+        let slow = limit(2, 3600);
+        let fast = limit(2, 60);
+        store.take("a", &slow, 2.0).await.unwrap();
+        store.take("b", &fast, 2.0).await.unwrap();
+        store.take("c", &slow, 2.0).await.unwrap();
+
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.buckets.len(), 2);
+        assert_eq!(buckets.by_full_at.len(), 2);
+        // `b` is full first, so it goes
+        assert!(buckets.buckets.contains_key("a"));
+        assert!(buckets.buckets.contains_key("c"));
+    }
+
+    #[actix_web::test]
+    async fn memory_store_sweeps_full_buckets() {
+        let store = MemoryStore::default();
+        let fast = RateLimit {
+            capacity: 1,
+            period: Duration::from_millis(10),
+        };
+        store.take("fast", &fast, 1.0).await.unwrap();
+        store.take("slow", &limit(1, 3600), 1.0).await.unwrap();
+        let retry_after = store.take("slow", &limit(1, 3600), 1.0).await.unwrap();
+        assert!(retry_after.is_some());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(store.sweep().await.unwrap(), 1);
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.buckets.len(), 1);
+        assert!(buckets.buckets.contains_key("slow"));
+    }
+
+    struct Uploads;
+
+    #[Object]
+    impl Uploads {
+        /// Charges the upload bucket the way `createAssetsBatch` does
+        async fn create_assets_batch(
+            &self,
+            ctx: &Context<'_>,
+            files: Vec<String>,
+        ) -> Result<usize> {
+            ctx.data::<RateLimiter>()?.check_upload(ctx, 1).await?;
+            Ok(files.len())
+        }
+    }
+
+    #[actix_web::test]
+    async fn large_batches_fit_the_default_upload_limit() {
+        let config = NamedTempFile::new().unwrap();
+        let vars = [
+            ("VEECERTS_CONFIG", config.path().to_str().unwrap()),
+            ("DATABASE_URL", "postgres://localhost/veecerts"),
+            ("SECRET_KEY", "secret"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let env = ENV::load_from(None, vars).ok().unwrap();
+        assert!(env.rate_limits.upload_client.unwrap().capacity < 300);
+        let schema = Schema::build(Uploads, EmptyMutation, EmptySubscription)
+            .data(RateLimiter::new(
+                &env.rate_limits,
+                DatabaseConnection::default(),
+            ))
+            .finish();
+
+        let files: Vec<String> = (0..300).map(|file| format!("\"{}.pdf\"", file)).collect();
+        let batch = format!("{{ createAssetsBatch(files: [{}]) }}", files.join(","));
+        let response = schema.execute(batch.as_str()).await;
+        assert!(response.is_ok(), "{:?}", response.errors);
+        assert_eq!(response.data.to_string(), "{createAssetsBatch: 300}");
+    }
+}
@@ -1,24 +1,55 @@
+use std::sync::Arc;
+
 use async_graphql::*;
 use sea_orm::DatabaseConnection;
 
+use super::{rate_limit::RateLimiter, state::AppState};
 use crate::apps::{
-    assets::graphql::{mutations::assets::AssetMutations, queries::assets::AssetQueries},
+    assets::graphql::{
+        mutations::{
+            assets::AssetMutations, exports::ExportMutations,
+            reconciliation::ReconciliationMutations,
+        },
+        queries::{
+            assets::AssetQueries, exports::ExportQueries, reconciliation::ReconciliationQueries,
+        },
+    },
     users::graphql::{
-        mutations::{auth::UsersAuthMutations, clients::UserClientMutations},
+        mutations::{auth::UsersAuthMutations, clients::UserClientMutations, users::UserMutations},
         queries::{clients::UserClientQueries, users::UserQueries},
     },
 };
 
 #[derive(MergedObject, Default)]
-pub struct Query(UserQueries, UserClientQueries, AssetQueries);
+pub struct Query(
+    UserQueries,
+    UserClientQueries,
+    AssetQueries,
+    ExportQueries,
+    ReconciliationQueries,
+);
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(UsersAuthMutations, UserClientMutations, AssetMutations);
+pub struct Mutation(
+    UsersAuthMutations,
+    UserMutations,
+    UserClientMutations,
+    AssetMutations,
+    ExportMutations,
+    ReconciliationMutations,
+);
 
 pub type AppSchema = Schema<Query, Mutation, EmptySubscription>;
 
-pub fn get_schema(db_conn: DatabaseConnection) -> AppSchema {
+pub fn get_schema(
+    db_conn: DatabaseConnection,
+    state: Arc<AppState>,
+    rate_limiter: Arc<RateLimiter>,
+) -> AppSchema {
     AppSchema::build(Query::default(), Mutation::default(), EmptySubscription)
         .data(db_conn)
+        .data(state)
+        .data(rate_limiter)
+        .extension(extensions::Tracing)
         .finish()
 }
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use async_graphql::*;
+use candid::Principal;
+use ic_agent::Agent;
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use pinata_sdk::PinataApi;
+
+use super::{mailer::Mailer, settings::ENV};
+use crate::apps::{
+    assets::utils::{
+        certificates::CertificateTemplate,
+        chain::ChainCache,
+        encryption::{load_key_provider, KeyProvider},
+        identity::{is_local_replica, load_identity},
+    },
+    users::utils::wallet::canister_signature_agent,
+};
+
+/// Calls to the NFT canister
+pub struct IcpClient {
+    /// Signs canister calls as the configured identity
+    pub agent: Agent,
+    pub canister_id: Principal,
+    /// Checks the certificates behind canister signatures, such as Internet
+    /// Identity's
+    pub signature_agent: Agent,
+}
+
+/// Settings and clients shared by every request, built once at startup.
+/// Resolvers get it from their context data, routes as `web::Data` and
+/// background jobs are handed an `Arc` of it. Helpers take it as an argument.
+pub struct AppState {
+    pub env: ENV,
+    icp: Option<IcpClient>,
+    pinata: Option<PinataApi>,
+    key_provider: Option<Box<dyn KeyProvider>>,
+    /// What the canister returned recently
+    pub chain_cache: ChainCache,
+    pub certificate_template: CertificateTemplate,
+    /// Notifies users by email, when SMTP is configured
+    pub mailer: Option<Mailer>,
+    pub http: reqwest::Client,
+    pub jwt_encoding_key: EncodingKey,
+    pub jwt_decoding_key: DecodingKey,
+}
+
+impl AppState {
+    /// Builds the clients of the configured integrations, fetching the root
+    /// key of a local replica
+    pub async fn init(env: ENV) -> Result<Arc<AppState>> {
+        let icp = match &env.icp {
+            Some(icp) => {
+                let agent = Agent::builder()
+                    .with_url(icp.agent_endpoint.clone())
+                    .with_arc_identity(load_identity(icp)?)
+                    .build()?;
+                if is_local_replica(&icp.agent_endpoint) {
+                    agent.fetch_root_key().await?;
+                }
+                Some(IcpClient {
+                    agent,
+                    canister_id: Principal::from_text(icp.canister_principal_id.as_str())?,
+                    signature_agent: canister_signature_agent(icp).await?,
+                })
+            }
+            None => None,
+        };
+        let pinata = match &env.pinata {
+            Some(pinata) => Some(PinataApi::new(&pinata.api_key, &pinata.api_secret)?),
+            None => None,
+        };
+        let mailer = match &env.smtp {
+            Some(smtp) => Some(Mailer::new(smtp)?),
+            None => None,
+        };
+
+        let state = AppState {
+            icp,
+            pinata,
+            key_provider: load_key_provider(&env)?,
+            chain_cache: ChainCache::new(env.chain_cache_ttl_secs),
+            certificate_template: CertificateTemplate::load(env.certificate_template.as_deref())?,
+            mailer,
+            http: reqwest::Client::new(),
+            jwt_encoding_key: EncodingKey::from_secret(env.secret_key.as_ref()),
+            jwt_decoding_key: DecodingKey::from_secret(env.secret_key.as_ref()),
+            env,
+        };
+        Ok(Arc::new(state))
+    }
+
+    pub fn icp(&self) -> Result<&IcpClient> {
+        match &self.icp {
+            Some(icp) => Ok(icp),
+            None => Err(Error::new(
+                "ICP is not configured, set ICP_AGENT_ENDPOINT and CANISTER_PRINCIPAL_ID",
+            )),
+        }
+    }
+
+    pub fn pinata(&self) -> Result<&PinataApi> {
+        match &self.pinata {
+            Some(pinata) => Ok(pinata),
+            None => Err(Error::new(
+                "Pinata is not configured, set PINATA_API_KEY and PINATA_API_SECRET",
+            )),
+        }
+    }
+
+    pub fn key_provider(&self) -> Result<&dyn KeyProvider> {
+        match &self.key_provider {
+            Some(provider) => Ok(provider.as_ref()),
+            None => Err(Error::new(
+                "KEY_FILE must be set to encrypt assets with the local key provider",
+            )),
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+};
+use entity::entities::user;
+use sea_orm::DatabaseConnection;
+use tracing::{field::Empty, Instrument, Span};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+use uuid::Uuid;
+
+use super::settings::{LogFormat, ENV};
+
+/// Accepted from callers and echoed on every response
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Instrumentation scope of the exported spans
+#[cfg(feature = "otel")]
+const TRACER_NAME: &str = "veecerts-backend";
+
+/// Keeps the trace exporter, if any, until spans are flushed on shutdown
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("Unable to flush traces: {}", err);
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber with the configured filter and format,
+/// exporting spans over OTLP when built with the `otel` feature
+pub fn init_tracing(env: &ENV) -> Telemetry {
+    let filter = EnvFilter::try_new(&env.log_level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt_layer = match env.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    #[cfg(feature = "otel")]
+    if let Some(settings) = &env.otel {
+        match otel::tracer_provider(settings) {
+            Ok(provider) => {
+                use opentelemetry::trace::TracerProvider;
+
+                let tracer = provider.tracer(TRACER_NAME);
+                opentelemetry::global::set_tracer_provider(provider.clone());
+                registry
+                    .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                    .init();
+                return Telemetry {
+                    provider: Some(provider),
+                };
+            }
+            Err(err) => eprintln!("Traces will not be exported: {}", err),
+        }
+    }
+
+    registry.init();
+    #[cfg(not(feature = "otel"))]
+    if env.otel.is_some() {
+        tracing::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set but the otel feature is not built");
+    }
+    Telemetry {
+        #[cfg(feature = "otel")]
+        provider: None,
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::time::SystemTime;
+
+    use actix_web::http::header::HeaderMap;
+    use opentelemetry::{global, propagation::Extractor, trace::TraceResult, Context, KeyValue};
+    use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, runtime::TokioCurrentThread, trace::TracerProvider,
+        Resource,
+    };
+
+    use crate::config::settings::{OtelSettings, OtlpProtocol};
+
+    /// Batches spans to the collector from a thread of its own, actix
+    /// running every worker on a current thread runtime
+    pub fn tracer_provider(settings: &OtelSettings) -> TraceResult<TracerProvider> {
+        let exporter = match settings.protocol {
+            OtlpProtocol::Grpc => SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(&settings.endpoint)
+                .build()?,
+            OtlpProtocol::HttpProtobuf => SpanExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpBinary)
+                .with_endpoint(format!(
+                    "{}/v1/traces",
+                    settings.endpoint.trim_end_matches('/')
+                ))
+                .build()?,
+        };
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, TokioCurrentThread)
+            .with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                settings.service_name.clone(),
+            )]))
+            .build())
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    /// Records a finished statement as a client span of the current request,
+    /// back-dated to when it was sent. Only the SQL is attached, never the
+    /// bound values.
+    pub fn record_query(info: &sea_orm::metric::Info) {
+        use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let parent = tracing::Span::current().context();
+        let ended = SystemTime::now();
+        let tracer = global::tracer(super::TRACER_NAME);
+        let mut span = tracer
+            .span_builder("db.query")
+            .with_kind(SpanKind::Client)
+            .with_start_time(ended - info.elapsed)
+            .with_attributes([
+                KeyValue::new("db.system", "postgresql"),
+                KeyValue::new("db.statement", info.statement.sql.clone()),
+            ])
+            .start_with_context(&tracer, &parent);
+        if info.failed {
+            span.set_status(Status::error("query failed"));
+        }
+        span.end_with_timestamp(ended);
+    }
+
+    /// The caller's trace from its W3C `traceparent` header
+    pub fn remote_context(headers: &HeaderMap) -> Context {
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+    }
+}
+
+/// Reuses the caller's request id when it is short and printable
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= 128
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+        })
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Runs every request in a span carrying its request id, which handlers
+/// fill in with the user, client and GraphQL operation
+pub async fn trace_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = request_id(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+        status = Empty,
+        user_id = Empty,
+        client_id = Empty,
+        operation = Empty,
+    );
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        span.set_parent(otel::remote_context(req.headers()));
+    }
+    let started = Instant::now();
+    let mut res = next.call(req).instrument(span.clone()).await?;
+
+    span.record("status", res.status().as_u16());
+    span.in_scope(|| {
+        tracing::info!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "request finished"
+        )
+    });
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(res)
+}
+
+/// Tags the current request with the authenticated user
+pub fn record_user(user: &user::Model) {
+    Span::current().record("user_id", user.id);
+}
+
+/// Tags the current request with the client a resolver loaded for the user
+pub fn record_client(client_id: i32) {
+    Span::current().record("client_id", client_id);
+}
+
+/// Tags the current request with its GraphQL operation
+pub fn record_operation(operation: &str) {
+    Span::current().record("operation", operation);
+}
+
+/// Logs every statement with its duration under the current request and,
+/// when traces are exported, records it as a span of that request
+pub fn trace_queries(db: &mut DatabaseConnection) {
+    db.set_metric_callback(|info| {
+        tracing::debug!(
+            target: "veecerts_backend::db",
+            elapsed_ms = info.elapsed.as_secs_f64() * 1000.0,
+            failed = info.failed,
+            sql = %info.statement.sql,
+            "query"
+        );
+        #[cfg(feature = "otel")]
+        otel::record_query(info);
+    });
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use actix_web::{dev::ServerHandle, web, App, HttpRequest, HttpResponse, HttpServer};
+    use opentelemetry::trace::{Tracer, TracerProvider};
+
+    use super::*;
+    use crate::config::settings::{OtelSettings, OtlpProtocol};
+
+    /// Content type and body of every export the stand-in collector got
+    type Received = Mutex<Vec<(String, web::Bytes)>>;
+
+    async fn collect(
+        req: HttpRequest,
+        body: web::Bytes,
+        received: web::Data<Received>,
+    ) -> HttpResponse {
+        let content_type = req
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        received.lock().unwrap().push((content_type, body));
+        HttpResponse::Ok()
+            .content_type("application/x-protobuf")
+            .finish()
+    }
+
+    /// A stand-in collector on a free port
+    fn start_collector() -> (u16, web::Data<Received>, ServerHandle) {
+        let received = web::Data::new(Received::default());
+        let collector_data = received.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(collector_data.clone())
+                .route("/v1/traces", web::post().to(collect))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (port, received, handle)
+    }
+
+    fn tracer_provider(port: u16) -> opentelemetry_sdk::trace::TracerProvider {
+        otel::tracer_provider(&OtelSettings {
+            endpoint: format!("http://127.0.0.1:{}", port),
+            protocol: OtlpProtocol::HttpProtobuf,
+            service_name: String::from("veecerts-test"),
+        })
+        .unwrap()
+    }
+
+    /// Flushes the batch, blocking until the collector answered
+    async fn flush(provider: opentelemetry_sdk::trace::TracerProvider, handle: ServerHandle) {
+        actix_web::rt::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        handle.stop(true).await;
+    }
+
+    fn contains(body: &[u8], part: &[u8]) -> bool {
+        body.windows(part.len()).any(|window| window == part)
+    }
+
+    #[actix_web::test]
+    async fn exports_spans_to_an_otlp_collector() {
+        let (port, received, handle) = start_collector();
+        let provider = tracer_provider(port);
+        provider
+            .tracer("veecerts-backend")
+            .in_span("exported-span", |_| {});
+        flush(provider, handle).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (content_type, body) = &received[0];
+        assert_eq!(content_type, "application/x-protobuf");
+        assert!(contains(body, b"exported-span"));
+        assert!(contains(body, b"veecerts-test"));
+    }
+
+    #[actix_web::test]
+    async fn exports_queries_as_spans_of_the_request() {
+        use opentelemetry::trace::TraceContextExt;
+        use sea_orm::{metric::Info, DbBackend, Statement};
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let (port, received, handle) = start_collector();
+        let provider = tracer_provider(port);
+        opentelemetry::global::set_tracer_provider(provider.clone());
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(TRACER_NAME)));
+
+        let request_span_id = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            let statement = Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                r#"SELECT "id" FROM "user" WHERE "email" = $1"#,
+                ["hidden@example.com".into()],
+            );
+            span.in_scope(|| {
+                otel::record_query(&Info {
+                    elapsed: std::time::Duration::from_millis(5),
+                    statement: &statement,
+                    failed: false,
+                })
+            });
+            span.context().span().span_context().span_id()
+        });
+        flush(provider, handle).await;
+
+        let received = received.lock().unwrap();
+        let body: Vec<u8> = received
+            .iter()
+            .flat_map(|(_, body)| body.to_vec())
+            .collect();
+        assert!(contains(&body, b"db.query"));
+        assert!(contains(
+            &body,
+            br#"SELECT "id" FROM "user" WHERE "email" = $1"#
+        ));
+        assert!(!contains(&body, b"hidden@example.com"));
+        // Once as the request's own id and once as the query's parent
+        let request_span_id = request_span_id.to_bytes();
+        let mentions = body
+            .windows(request_span_id.len())
+            .filter(|window| *window == request_span_id)
+            .count();
+        assert_eq!(mentions, 2);
+    }
+
+    #[actix_web::test]
+    async fn continues_the_callers_trace() {
+        use actix_web::{middleware::from_fn, test};
+
+        let (port, received, handle) = start_collector();
+        let provider = tracer_provider(port);
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(TRACER_NAME)));
+        let guard = tracing::subscriber::set_default(subscriber);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(trace_request))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let request = test::TestRequest::get()
+            .uri("/")
+            .insert_header((
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success());
+        drop(app);
+        drop(guard);
+        flush(provider, handle).await;
+
+        let received = received.lock().unwrap();
+        let body: Vec<u8> = received
+            .iter()
+            .flat_map(|(_, body)| body.to_vec())
+            .collect();
+        assert!(contains(&body, b"request"));
+        let trace_id = hex::decode("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        let parent_span_id = hex::decode("00f067aa0ba902b7").unwrap();
+        assert!(contains(&body, &trace_id));
+        assert!(contains(&body, &parent_span_id));
+    }
+}
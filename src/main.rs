@@ -1,20 +1,35 @@
-use actix_cors::Cors;
-use actix_web::{
-    get, http::header::HeaderMap, post, web, App, HttpRequest, HttpResponse, HttpServer,
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
-use apps::users::utils::auth::decode_user_auth_token;
-use async_graphql::{
-    http::{graphiql_plugin_explorer, GraphiQLSource},
-    Result,
+
+use actix_cors::Cors;
+use actix_web::{get, middleware::from_fn, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use apps::{
+    assets::{
+        routes::{
+            asset_qr_code, download_asset, download_certificate, download_export, folder_qr_code,
+            verify_asset, verify_folder,
+        },
+        utils::{contract::Contract, identity::load_identity},
+    },
+    users::utils::auth::get_user_from_header,
 };
+use async_graphql::http::{graphiql_plugin_explorer, GraphiQLSource};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use config::{
     database::connect_db,
+    health::{healthz, readyz},
+    housekeeping::spawn_housekeeping,
+    metrics::{prometheus_metrics, Metrics, OperationLabels},
+    rate_limit::{ClientIp, RateLimiter},
     schema::{get_schema, AppSchema},
     settings::ENV,
+    state::AppState,
+    telemetry::{init_tracing, record_operation, trace_request},
 };
 use dotenv::dotenv;
-use entity::entities::user;
 use sea_orm::DatabaseConnection;
 pub mod apps;
 pub mod config;
@@ -31,35 +46,16 @@ async fn graphiql() -> HttpResponse {
         )
 }
 
-async fn get_user_from_header(
-    headers: &HeaderMap,
-    db: &DatabaseConnection,
-) -> Result<Option<user::Model>> {
-    let token_str = headers
-        .get("Authorization")
-        .map(|value| Some(value.as_ref()));
-
-    if let Some(token_str) = token_str {
-        match token_str {
-            Some(token) => {
-                let token = decode_user_auth_token(String::from_utf8(token.to_vec())?, db).await?;
-                Ok(token)
-            }
-            _ => Ok(None),
-        }
-    } else {
-        Ok(None)
-    }
-}
-
 #[post("/")]
 async fn index(
     schema: web::Data<AppSchema>,
     db: web::Data<DatabaseConnection>,
+    state: web::Data<AppState>,
+    operation_labels: web::Data<OperationLabels>,
     req: HttpRequest,
     gql_request: GraphQLRequest,
 ) -> GraphQLResponse {
-    let user = match get_user_from_header(req.headers(), &db).await {
+    let user = match get_user_from_header(req.headers(), &db, &state).await {
         Ok(user) => user,
         Err(err) => {
             return async_graphql::Response::from_errors(vec![
@@ -68,26 +64,129 @@ async fn index(
             .into()
         }
     };
-    let request = gql_request.into_inner().data(user);
-    schema.execute(request).await.into()
+    let client_ip = ClientIp::of(&req, state.env.rate_limits.trust_forwarded);
+    let mut request = gql_request.into_inner().data(user).data(client_ip);
+    let operation = operation_labels.label(&mut request);
+    record_operation(&operation);
+    let started = Instant::now();
+    let response = schema.execute(request).await;
+
+    let metrics = Metrics::get();
+    metrics
+        .graphql_request_duration
+        .with_label_values(&[&operation])
+        .observe(started.elapsed().as_secs_f64());
+    let status = if response.is_ok() { "ok" } else { "error" };
+    metrics
+        .graphql_requests
+        .with_label_values(&[&operation, status])
+        .inc();
+    response.into()
+}
+
+/// Validates the settings and the ICP identity without starting the server
+fn check_config(config_path: Option<&Path>) -> bool {
+    let env = match ENV::load(config_path) {
+        Ok(env) => env,
+        Err(errors) => {
+            eprintln!("{}", errors);
+            return false;
+        }
+    };
+    if let Some(icp) = &env.icp {
+        match load_identity(icp).map(|identity| identity.sender()) {
+            Ok(Ok(principal)) => println!(
+                "ICP: {} on {} as {}",
+                icp.canister_principal_id, icp.network, principal
+            ),
+            Ok(Err(err)) => {
+                eprintln!("Invalid configuration:\n  - ICP identity: {}", err);
+                return false;
+            }
+            Err(err) => {
+                eprintln!("Invalid configuration:\n  - ICP identity: {}", err.message);
+                return false;
+            }
+        }
+    } else {
+        println!("ICP: disabled");
+    }
+    println!(
+        "Pinata: {}",
+        if env.pinata.is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    println!(
+        "Email: {}",
+        if env.smtp.is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    match &env.config_file {
+        Some(path) => println!("Configuration in {} is valid", path.display()),
+        None => println!("Configuration is valid"),
+    }
+    true
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let env = ENV::init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|position| args.get(position + 1))
+        .map(PathBuf::from);
+    if args.iter().any(|arg| arg == "--check-config") {
+        std::process::exit(if check_config(config_path.as_deref()) {
+            0
+        } else {
+            1
+        });
+    }
+
+    let env = ENV::load(config_path.as_deref()).unwrap_or_else(|errors| {
+        eprintln!("{}", errors);
+        std::process::exit(1);
+    });
+    let telemetry = init_tracing(&env);
+    let state = AppState::init(env)
+        .await
+        .expect("App state could not be built");
+    let env = &state.env;
     let port = env.port;
-    let addrs = env.addrs;
-    let db_conn = connect_db().await.expect("Database connection failed");
+    let addrs = env.addrs.clone();
+    let db_conn = connect_db(&env.db_url)
+        .await
+        .expect("Database connection failed");
+    let rate_limiter = Arc::new(RateLimiter::new(&env.rate_limits, db_conn.clone()));
+    spawn_housekeeping(db_conn.clone(), state.clone(), rate_limiter.clone());
+    let schema = get_schema(db_conn.clone(), state.clone(), rate_limiter);
+    let operation_labels = web::Data::new(OperationLabels::new(&schema));
 
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .with_test_writer()
-        .init();
+    if let Some(icp) = &env.icp {
+        let principal = Contract::principal(&state).expect("ICP identity could not be loaded");
+        println!(
+            "Calling canister {} on the {} network as {}",
+            icp.canister_principal_id, icp.network, principal
+        );
+    } else {
+        println!("ICP is not configured, minting is disabled");
+    }
+    if env.pinata.is_none() {
+        println!("Pinata is not configured, uploads are disabled");
+    }
 
     println!("Server running on http://{}:{}", &addrs, &port);
     HttpServer::new(move || {
-        let allowed_origins = ENV::init().allowed_origns;
+        let allowed_origins = state.env.allowed_origns.clone();
         let cors = Cors::default()
             .allow_any_method()
             .allow_any_header()
@@ -103,12 +202,28 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .wrap(cors)
+            .wrap(from_fn(trace_request))
             .app_data(web::Data::new(db_conn.clone()))
-            .app_data(web::Data::new(get_schema(db_conn.clone())))
+            .app_data(web::Data::from(state.clone()))
+            .app_data(web::Data::new(schema.clone()))
+            .app_data(operation_labels.clone())
+            .service(healthz)
+            .service(readyz)
+            .service(prometheus_metrics)
             .service(graphiql)
             .service(index)
+            .service(download_export)
+            .service(download_asset)
+            .service(download_certificate)
+            .service(verify_folder)
+            .service(verify_asset)
+            .service(asset_qr_code)
+            .service(folder_qr_code)
     })
     .bind((addrs, port))?
     .run()
-    .await
+    .await?;
+
+    telemetry.shutdown();
+    Ok(())
 }